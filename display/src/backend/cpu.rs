@@ -0,0 +1,222 @@
+use super::{Backend, CaptureCallback, TileFactory};
+use crate::color::to_srgba8;
+use crate::draw_list::Primitive;
+use crate::error::Result;
+use crate::export::LinearImage;
+use crate::layer::BlendMode;
+use nannou::color::LinSrgba;
+use nannou::geom::{pt2, Point2, Rect, Vec2};
+use nannou::image::{Rgba, RgbaImage};
+use std::ops::Range;
+
+// Sub-pixel sample positions of the standard 4x MSAA pattern, the sample count nannou uses by default.
+const SAMPLES: [(f32, f32); 4] = [
+  (-0.125, -0.375),
+  (0.375, -0.125),
+  (-0.375, 0.125),
+  (0.125, 0.375),
+];
+
+pub struct CpuBackend {
+  size: [u32; 2],
+  // Linear RGBA values, row by row from the top left corner.
+  pixels: Vec<[f32; 4]>,
+  font: rusttype::Font<'static>,
+  // Blend mode of the primitives being rendered.
+  blend: BlendMode,
+}
+
+impl CpuBackend {
+  pub fn new(size: [u32; 2]) -> Self {
+    Self {
+      size,
+      pixels: vec![[0.; 4]; (size[0] * size[1]) as usize],
+      font: nannou::text::font::default_notosans(),
+      blend: BlendMode::Normal,
+    }
+  }
+
+  // Convert the target to non-linear 8-bit sRGBA, like the `TextureCapturer` does.
+  pub fn to_image(&self) -> RgbaImage {
+    let [w, h] = self.size;
+    RgbaImage::from_fn(w, h, |x, y| {
+      let [red, green, blue, alpha] = self.pixels[(y * w + x) as usize];
+      Rgba(to_srgba8(&LinSrgba::new(red, green, blue, alpha)))
+    })
+  }
+
+  fn fill(&mut self, color: &LinSrgba) {
+    let pixel = [color.red, color.green, color.blue, color.alpha];
+    self.pixels.iter_mut().for_each(|p| *p = pixel);
+  }
+
+  // Blend `color` over the pixel with the current blend mode, weighted by `coverage`.
+  fn blend(&mut self, x: u32, y: u32, color: &LinSrgba, coverage: f32) {
+    let pixel = &mut self.pixels[(y * self.size[0] + x) as usize];
+    let rgb = [color.red, color.green, color.blue];
+    self.blend.blend(pixel, rgb, color.alpha * coverage);
+  }
+
+  // Range of pixels touched by `rect`, `rect` being expressed in nannou coordinates.
+  fn pixel_bounds(&self, rect: Rect) -> (Range<u32>, Range<u32>) {
+    let [w, h] = [self.size[0] as f32, self.size[1] as f32];
+    let clamp = |value: f32, max: f32| value.clamp(0., max) as u32;
+    let x = clamp((rect.left() + w / 2.).floor(), w)..clamp((rect.right() + w / 2.).ceil(), w);
+    let y = clamp((h / 2. - rect.top()).floor(), h)..clamp((h / 2. - rect.bottom()).ceil(), h);
+    (x, y)
+  }
+
+  // Cover every pixel of `bounds` with `color` according to how many samples are `inside`.
+  fn rasterize<F: Fn(Point2) -> bool>(&mut self, bounds: Rect, color: &LinSrgba, inside: F) {
+    let [w, h] = [self.size[0] as f32, self.size[1] as f32];
+    let (xs, ys) = self.pixel_bounds(bounds);
+    for y in ys {
+      for x in xs.clone() {
+        let covered = SAMPLES
+          .iter()
+          .filter(|(dx, dy)| {
+            inside(pt2(
+              x as f32 + 0.5 + dx - w / 2.,
+              h / 2. - (y as f32 + 0.5 + dy),
+            ))
+          })
+          .count();
+        if covered > 0 {
+          self.blend(x, y, color, covered as f32 / SAMPLES.len() as f32);
+        }
+      }
+    }
+  }
+
+  fn draw_ellipse(&mut self, xy: Point2, wh: Vec2, color: &LinSrgba) {
+    let radii = wh / 2.;
+    let bounds = Rect::from_xy_wh(xy, wh);
+    self.rasterize(bounds, color, |point| {
+      let d = (point - xy) / radii;
+      d.length_squared() <= 1.
+    });
+  }
+
+  fn draw_line(&mut self, start: Point2, end: Point2, weight: f32, color: &LinSrgba) {
+    let direction = end - start;
+    let length = direction.length();
+    if length == 0. {
+      return;
+    }
+    let direction = direction / length;
+    let bounds = Rect::from_corners(start, end).pad(-weight / 2.);
+    self.rasterize(bounds, color, |point| {
+      let along = (point - start).dot(direction);
+      let across = (point - start).perp_dot(direction);
+      (0. ..=length).contains(&along) && across.abs() <= weight / 2.
+    });
+  }
+
+  // Lay the text on a single line centered in `rect`, as nannou does by default.
+  fn draw_text(&mut self, text: &str, rect: Rect, font_size: u32, color: &LinSrgba) {
+    let [w, h] = [self.size[0] as i32, self.size[1] as i32];
+    let font = self.font.clone();
+    let scale = rusttype::Scale::uniform(font_size as f32);
+    let v_metrics = font.v_metrics(scale);
+    let width = font
+      .layout(text, scale, rusttype::point(0., 0.))
+      .last()
+      .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+      .unwrap_or(0.);
+    let origin = rusttype::point(
+      rect.x() - width / 2. + w as f32 / 2.,
+      h as f32 / 2. - rect.y() + (v_metrics.ascent + v_metrics.descent) / 2.,
+    );
+    for glyph in font.layout(text, scale, origin) {
+      if let Some(bounding_box) = glyph.pixel_bounding_box() {
+        glyph.draw(|gx, gy, coverage| {
+          let x = bounding_box.min.x + gx as i32;
+          let y = bounding_box.min.y + gy as i32;
+          if (0..w).contains(&x) && (0..h).contains(&y) {
+            self.blend(x as u32, y as u32, color, coverage);
+          }
+        });
+      }
+    }
+  }
+}
+
+impl Backend for CpuBackend {
+  fn size(&self) -> [u32; 2] {
+    self.size
+  }
+
+  fn with_size(&self, size: [u32; 2]) -> Box<dyn Backend> {
+    Box::new(CpuBackend::new(size))
+  }
+
+  fn tile_factory(&self) -> TileFactory {
+    Box::new(|size| Box::new(CpuBackend::new(size)))
+  }
+
+  fn render(&mut self, primitives: &[Primitive], blend: BlendMode) {
+    self.blend = blend;
+    for primitive in primitives {
+      match primitive {
+        Primitive::Background { color } => self.fill(color),
+        Primitive::Ellipse { xy, wh, color } => self.draw_ellipse(*xy, *wh, color),
+        Primitive::Line {
+          start,
+          end,
+          weight,
+          color,
+        } => self.draw_line(*start, *end, *weight, color),
+        Primitive::Text {
+          text,
+          rect,
+          font_size,
+          color,
+        } => self.draw_text(text, *rect, *font_size, color),
+      }
+    }
+  }
+
+  fn composite(&mut self, image: &LinearImage, blend: BlendMode) {
+    for (pixel, source) in self.pixels.iter_mut().zip(image.pixels()) {
+      let [red, green, blue, alpha] = source.0;
+      blend.blend(pixel, [red, green, blue], alpha);
+    }
+  }
+
+  fn capture(&mut self, callback: CaptureCallback) -> Result<()> {
+    callback(Ok(self.to_image()));
+    Ok(())
+  }
+
+  fn read_linear(&mut self) -> Result<LinearImage> {
+    let [w, h] = self.size;
+    Ok(LinearImage::from_fn(w, h, |x, y| {
+      Rgba(self.pixels[(y * w + x) as usize])
+    }))
+  }
+
+  fn wait(&self) -> Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::draw_list::DrawList;
+
+  #[test]
+  fn renders_a_frame_without_a_window() {
+    let draw = DrawList::new();
+    draw.background(LinSrgba::new(0., 0., 0., 1.));
+    draw.ellipse(pt2(-8., 0.), Vec2::splat(6.), LinSrgba::new(1., 0., 0., 1.));
+    draw.line(pt2(4., -6.), pt2(4., 6.), 2., LinSrgba::new(0., 0., 1., 1.));
+    let mut backend = CpuBackend::new([32, 16]);
+    backend.render(&draw.primitives(), BlendMode::Normal);
+    let image = backend.to_image();
+    // The origin of the drawing is in the middle of the image, its y axis pointing up.
+    assert_eq!(image.get_pixel(8, 7).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(20, 8).0, [0, 0, 255, 255]);
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+  }
+}
@@ -0,0 +1,434 @@
+use super::{Backend, CaptureCallback, TileFactory};
+use crate::draw_list::Primitive;
+use crate::error::{Error, Result};
+use crate::export::LinearImage;
+use crate::layer::BlendMode;
+use crate::preview::letterbox;
+use nannou::image::Rgba;
+use std::cell::RefCell;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+// Number of samples used when there is no window to pick them.
+const OFFSCREEN_SAMPLE_COUNT: u32 = 4;
+// Layout of the text drawn over the preview, in pixels.
+const OVERLAY_FONT_SIZE: u32 = 16;
+const OVERLAY_PADDING: f32 = 16.;
+
+pub struct GpuBackend {
+  device_queue_pair: Arc<nannou::wgpu::DeviceQueuePair>,
+  sample_count: u32,
+  texture: nannou::wgpu::Texture,
+  texture_capturer: nannou::wgpu::TextureCapturer,
+  // Resolves the texture to a single sampled one the preview can be drawn from.
+  texture_reshaper: nannou::wgpu::TextureReshaper,
+  // The texture scaled down to the size it is shown at, created on the first preview and again when
+  // the window is resized.
+  preview_texture: RefCell<Option<nannou::wgpu::Texture>>,
+  // Draws the preview into the window frame, rebuilt when the frame changes.
+  preview_renderer: RefCell<
+    Option<(
+      nannou::wgpu::TextureDescriptor<'static>,
+      nannou::draw::Renderer,
+    )>,
+  >,
+  // Holds the images composited on the texture, created on first use.
+  upload_texture: Option<nannou::wgpu::Texture>,
+  draw: nannou::Draw,
+  renderer: nannou::draw::Renderer,
+}
+
+impl GpuBackend {
+  pub fn new(window: &nannou::window::Window, texture_size: [u32; 2]) -> Self {
+    // Retrieve the wgpu device.
+    let device_queue_pair = window.device_queue_pair().clone();
+    Self::from_device(device_queue_pair, window.msaa_samples(), texture_size)
+  }
+
+  // Render on the GPU without any window.
+  pub fn offscreen(texture_size: [u32; 2]) -> Option<Self> {
+    let instance = nannou::wgpu::Instance::new(nannou::wgpu::DEFAULT_BACKENDS);
+    let options = nannou::wgpu::RequestAdapterOptions {
+      power_preference: nannou::wgpu::DEFAULT_POWER_PREFERENCE,
+      force_fallback_adapter: false,
+      compatible_surface: None,
+    };
+    let adapter = nannou::wgpu::AdapterMap::default().get_or_request(options, &instance)?;
+    let device_queue_pair =
+      adapter.get_or_request_device(nannou::wgpu::default_device_descriptor());
+    Some(Self::from_device(
+      device_queue_pair,
+      OFFSCREEN_SAMPLE_COUNT,
+      texture_size,
+    ))
+  }
+
+  pub fn from_device(
+    device_queue_pair: Arc<nannou::wgpu::DeviceQueuePair>,
+    sample_count: u32,
+    texture_size: [u32; 2],
+  ) -> Self {
+    let device = device_queue_pair.device();
+    // Create the custom texture.
+    let texture = nannou::wgpu::TextureBuilder::new()
+      .size(texture_size)
+      // The texture will be used as the RENDER_ATTACHMENT for the `Draw` render pass.
+      // It will also be SAMPLED by the `TextureCapturer` and `TextureResizer`.
+      .usage(
+        nannou::wgpu::TextureUsages::RENDER_ATTACHMENT
+          | nannou::wgpu::TextureUsages::TEXTURE_BINDING,
+      )
+      // Use nannou's default multisampling sample count.
+      .sample_count(sample_count)
+      // Use a spacious 16-bit linear sRGBA format suitable for high quality drawing.
+      .format(nannou::wgpu::TextureFormat::Rgba16Float)
+      .build(device);
+    // Create `Draw` instance and a renderer for it.
+    let draw = nannou::Draw::new();
+    let descriptor = texture.descriptor();
+    let renderer =
+      nannou::draw::RendererBuilder::new().build_from_texture_descriptor(device, descriptor);
+    // Create the texture capturer.
+    let texture_capturer = nannou::wgpu::TextureCapturer::default();
+    // Create the texture reshaper resolving the texture for the preview.
+    let texture_view = texture.view().build();
+    let texture_sample_type = texture.sample_type();
+    let texture_reshaper = nannou::wgpu::TextureReshaper::new(
+      device,
+      &texture_view,
+      sample_count,
+      texture_sample_type,
+      1,
+      nannou::Frame::TEXTURE_FORMAT,
+    );
+
+    Self {
+      device_queue_pair,
+      sample_count,
+      texture,
+      draw,
+      renderer,
+      texture_capturer,
+      texture_reshaper,
+      preview_texture: RefCell::new(None),
+      preview_renderer: RefCell::new(None),
+      upload_texture: None,
+    }
+  }
+
+  // Render the current content of `draw` to the texture.
+  fn submit(&mut self) {
+    let device = self.device_queue_pair.device();
+    let ce_desc = nannou::wgpu::CommandEncoderDescriptor {
+      label: Some("texture renderer"),
+    };
+    let mut encoder = device.create_command_encoder(&ce_desc);
+    self
+      .renderer
+      .render_to_texture(device, &mut encoder, &self.draw, &self.texture);
+    self
+      .device_queue_pair
+      .queue()
+      .submit(Some(encoder.finish()));
+  }
+
+  fn replay(&self, primitives: &[Primitive], blend: BlendMode) {
+    self.draw.reset();
+    let draw = self.draw.color_blend(blend.component());
+    let premultiplied: Vec<Primitive>;
+    let primitives = match blend.premultiplied() {
+      true => {
+        premultiplied = primitives.iter().map(Primitive::premultiplied).collect();
+        &premultiplied
+      }
+      false => primitives,
+    };
+    for primitive in primitives {
+      draw_primitive(&draw, primitive);
+    }
+  }
+}
+
+// Draw a primitive with the nannou API, in the coordinates of the texture.
+fn draw_primitive(draw: &nannou::Draw, primitive: &Primitive) {
+  match primitive {
+    Primitive::Background { color } => {
+      draw.background().color(*color);
+    }
+    Primitive::Ellipse { xy, wh, color } => {
+      draw.ellipse().xy(*xy).wh(*wh).color(*color);
+    }
+    Primitive::Line {
+      start,
+      end,
+      weight,
+      color,
+    } => {
+      draw
+        .line()
+        .start(*start)
+        .end(*end)
+        .weight(*weight)
+        .color(*color);
+    }
+    Primitive::Text {
+      text,
+      rect,
+      font_size,
+      color,
+    } => {
+      draw
+        .text(text)
+        .font_size(*font_size)
+        .x(rect.x())
+        .y(rect.y())
+        .wh(rect.wh())
+        .color(*color);
+    }
+  }
+}
+
+impl Backend for GpuBackend {
+  fn size(&self) -> [u32; 2] {
+    self.texture.size()
+  }
+
+  fn with_size(&self, size: [u32; 2]) -> Box<dyn Backend> {
+    Box::new(GpuBackend::from_device(
+      self.device_queue_pair.clone(),
+      self.sample_count,
+      size,
+    ))
+  }
+
+  fn tile_factory(&self) -> TileFactory {
+    let (device_queue_pair, sample_count) = (self.device_queue_pair.clone(), self.sample_count);
+    Box::new(move |size| {
+      Box::new(GpuBackend::from_device(
+        device_queue_pair.clone(),
+        sample_count,
+        size,
+      ))
+    })
+  }
+
+  fn render(&mut self, primitives: &[Primitive], blend: BlendMode) {
+    self.replay(primitives, blend);
+    self.submit();
+  }
+
+  fn composite(&mut self, image: &LinearImage, blend: BlendMode) {
+    let [w, h] = self.size();
+    let device = self.device_queue_pair.device();
+    let upload_texture = self.upload_texture.get_or_insert_with(|| {
+      nannou::wgpu::TextureBuilder::new()
+        .size([w, h])
+        .usage(nannou::wgpu::TextureUsages::TEXTURE_BINDING | nannou::wgpu::TextureUsages::COPY_DST)
+        .format(nannou::wgpu::TextureFormat::Rgba16Float)
+        .build(device)
+    });
+    // The texture stores 16-bit floats in little endian order.
+    let data: Vec<u8> = image
+      .as_raw()
+      .iter()
+      .flat_map(|value| half::f16::from_f32(*value).to_le_bytes())
+      .collect();
+    let layout = nannou::wgpu::ImageDataLayout {
+      offset: 0,
+      bytes_per_row: NonZeroU32::new(w * 8),
+      rows_per_image: NonZeroU32::new(h),
+    };
+    self.device_queue_pair.queue().write_texture(
+      upload_texture.as_image_copy(),
+      &data,
+      layout,
+      upload_texture.extent(),
+    );
+    self.draw.reset();
+    self
+      .draw
+      .color_blend(blend.component())
+      .texture(upload_texture)
+      .w_h(w as f32, h as f32);
+    self.submit();
+  }
+
+  fn capture(&mut self, callback: CaptureCallback) -> Result<()> {
+    let device = self.device_queue_pair.device();
+    let ce_desc = nannou::wgpu::CommandEncoderDescriptor {
+      label: Some("texture capturer"),
+    };
+    let mut encoder = device.create_command_encoder(&ce_desc);
+    // Take a snapshot of the texture. The capturer will do the following
+    // 1. Resolve the texture to a non-multisampled texture if necessary.
+    // 2. Convert the format to non-linear 8-bit sRGBA ready for image storage.
+    // 3. Copy the result to a buffer ready to be mapped for reading.
+    let snapshot = self
+      .texture_capturer
+      .capture(device, &mut encoder, &self.texture);
+    // Submit the commands for our texture capture to the GPU.
+    self
+      .device_queue_pair
+      .queue()
+      .submit(Some(encoder.finish()));
+    // Submit a function for handing our snapshot to the callback.
+    // NOTE: It is essential that the commands for capturing the snapshot are `submit`ted before we
+    // attempt to read the snapshot - otherwise we will read a blank texture!
+    snapshot
+      .read(move |result| {
+        let image = result
+          .map(|image| image.to_owned())
+          .map_err(|err| Error::Capture(format!("failed to map texture memory, {:?}", err)));
+        callback(image);
+      })
+      .map_err(|_| Error::Capture("timed out waiting for a capture worker".to_string()))
+  }
+
+  fn read_linear(&mut self) -> Result<LinearImage> {
+    let device = self.device_queue_pair.device();
+    let [w, h] = self.texture.size();
+    // Resolve the texture to a non-multisampled one, keeping its 16-bit float format.
+    let format = nannou::wgpu::TextureFormat::Rgba16Float;
+    let resolved = nannou::wgpu::TextureBuilder::new()
+      .size([w, h])
+      .usage(nannou::wgpu::TextureUsages::RENDER_ATTACHMENT | nannou::wgpu::TextureUsages::COPY_SRC)
+      .sample_count(1)
+      .format(format)
+      .build(device);
+    let texture_view = self.texture.view().build();
+    let resolver = nannou::wgpu::TextureReshaper::new(
+      device,
+      &texture_view,
+      self.sample_count,
+      self.texture.sample_type(),
+      1,
+      format,
+    );
+    // Rows copied to a buffer must be aligned, each pixel holds 4 channels of 2 bytes.
+    let align = nannou::wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let bytes_per_row = (w * 8).div_ceil(align) * align;
+    let buffer = device.create_buffer(&nannou::wgpu::BufferDescriptor {
+      label: Some("linear texture reader"),
+      size: (bytes_per_row * h) as u64,
+      usage: nannou::wgpu::BufferUsages::MAP_READ | nannou::wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+    let ce_desc = nannou::wgpu::CommandEncoderDescriptor {
+      label: Some("linear texture reader"),
+    };
+    let mut encoder = device.create_command_encoder(&ce_desc);
+    resolver.encode_render_pass(&resolved.view().build(), &mut encoder);
+    encoder.copy_texture_to_buffer(
+      resolved.as_image_copy(),
+      nannou::wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: nannou::wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: NonZeroU32::new(bytes_per_row),
+          rows_per_image: NonZeroU32::new(h),
+        },
+      },
+      resolved.extent(),
+    );
+    self
+      .device_queue_pair
+      .queue()
+      .submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(nannou::wgpu::MapMode::Read);
+    device.poll(nannou::wgpu::Maintain::Wait);
+    futures::executor::block_on(mapping)
+      .map_err(|err| Error::Capture(format!("failed to map texture memory, {:?}", err)))?;
+    let bytes = slice.get_mapped_range();
+    let image = LinearImage::from_fn(w, h, |x, y| {
+      let offset = (y * bytes_per_row + x * 8) as usize;
+      let channel = |i: usize| {
+        half::f16::from_le_bytes([bytes[offset + 2 * i], bytes[offset + 2 * i + 1]]).to_f32()
+      };
+      Rgba([channel(0), channel(1), channel(2), channel(3)])
+    });
+    drop(bytes);
+    buffer.unmap();
+    Ok(image)
+  }
+
+  fn wait(&self) -> Result<()> {
+    let device = self.device_queue_pair.device();
+    self
+      .texture_capturer
+      .await_active_snapshots(device)
+      .map_err(|_| Error::Capture("timed out waiting for the captures to complete".to_string()))
+  }
+
+  // Draw the texture in the middle of the frame, keeping its aspect ratio.
+  fn view(&self, frame: nannou::frame::Frame, overlay: &[String], primitives: &[Primitive]) {
+    let device = self.device_queue_pair.device();
+    let [w, h] = letterbox(self.texture.size(), frame.texture_size());
+    // The reshaper scales the texture down to the letterbox, never up.
+    let [max_w, max_h] = self.texture.size();
+    let size = [
+      (w.round() as u32).clamp(1, max_w),
+      (h.round() as u32).clamp(1, max_h),
+    ];
+    let mut preview_texture = self.preview_texture.borrow_mut();
+    if preview_texture.as_ref().map(|texture| texture.size()) != Some(size) {
+      *preview_texture = Some(
+        nannou::wgpu::TextureBuilder::new()
+          .size(size)
+          .usage(
+            nannou::wgpu::TextureUsages::RENDER_ATTACHMENT
+              | nannou::wgpu::TextureUsages::TEXTURE_BINDING,
+          )
+          .format(nannou::Frame::TEXTURE_FORMAT)
+          .build(device),
+      );
+    }
+    let preview_texture = preview_texture.as_ref().unwrap();
+    {
+      let mut encoder = frame.command_encoder();
+      let preview_view = preview_texture.view().build();
+      self
+        .texture_reshaper
+        .encode_render_pass(&preview_view, &mut encoder);
+    }
+    frame.clear(nannou::color::BLACK);
+    let draw = nannou::Draw::new();
+    draw.texture(preview_texture).w_h(w, h);
+    let scaled = draw.scale(w / max_w as f32);
+    for primitive in primitives {
+      draw_primitive(&scaled, primitive);
+    }
+    if !overlay.is_empty() {
+      let [frame_w, frame_h] = frame.texture_size().map(|side| side as f32);
+      let area = nannou::geom::Rect::from_w_h(frame_w, frame_h).pad(OVERLAY_PADDING);
+      draw
+        .rect()
+        .w_h(frame_w, frame_h)
+        .color(nannou::color::rgba(0., 0., 0., 0.6));
+      draw
+        .text(&overlay.join("\n"))
+        .font_size(OVERLAY_FONT_SIZE)
+        .left_justify()
+        .align_text_top()
+        .xy(area.xy())
+        .wh(area.wh())
+        .color(nannou::color::WHITE);
+    }
+
+    let descriptor = frame.texture().descriptor();
+    let mut preview_renderer = self.preview_renderer.borrow_mut();
+    let outdated = match &*preview_renderer {
+      Some((built_for, _)) => !nannou::wgpu::texture_descriptor_eq(built_for, descriptor),
+      None => true,
+    };
+    if outdated {
+      let renderer =
+        nannou::draw::RendererBuilder::new().build_from_texture_descriptor(device, descriptor);
+      *preview_renderer = Some((descriptor.clone(), renderer));
+    }
+    let (_, renderer) = preview_renderer.as_mut().unwrap();
+    // The frame is drawn in pixels rather than points.
+    renderer.render_to_frame(device, &draw, 1., &frame);
+  }
+}
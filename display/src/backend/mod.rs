@@ -0,0 +1,49 @@
+mod cpu;
+mod gpu;
+
+pub use cpu::CpuBackend;
+pub use gpu::GpuBackend;
+
+use crate::draw_list::Primitive;
+use crate::error::Result;
+use crate::export::LinearImage;
+use crate::layer::BlendMode;
+use nannou::image::RgbaImage;
+
+// Receives a non-linear 8-bit sRGBA copy of the target once it is available.
+pub type CaptureCallback = Box<dyn FnOnce(Result<RgbaImage>) + Send>;
+
+// Creates blank targets of the same kind as a backend from any thread, for the given size.
+pub type TileFactory = Box<dyn Fn([u32; 2]) -> Box<dyn Backend> + Send>;
+
+// A render target the `DisplayDriver` replays its draw list on.
+pub trait Backend {
+  // Size of the target, in pixels.
+  fn size(&self) -> [u32; 2];
+
+  // Create a blank target of the same kind with another size.
+  fn with_size(&self, size: [u32; 2]) -> Box<dyn Backend>;
+
+  // Create blank targets of the same kind from another thread, where tiles are rendered.
+  fn tile_factory(&self) -> TileFactory;
+
+  // Draw the primitives on top of the current content of the target.
+  fn render(&mut self, primitives: &[Primitive], blend: BlendMode);
+
+  // Draw a linear image covering the whole target on top of its current content.
+  fn composite(&mut self, image: &LinearImage, blend: BlendMode);
+
+  // Capture the current content of the target.
+  fn capture(&mut self, callback: CaptureCallback) -> Result<()>;
+
+  // Read the current content of the target back as linear floating point values, blocking until
+  // it is available.
+  fn read_linear(&mut self) -> Result<LinearImage>;
+
+  // Block until every pending capture has completed.
+  fn wait(&self) -> Result<()>;
+
+  // Display the target in the preview window, if there is one, with the primitives drawn in the
+  // coordinates of the target then the lines of `overlay` on top.
+  fn view(&self, _frame: nannou::frame::Frame, _overlay: &[String], _primitives: &[Primitive]) {}
+}
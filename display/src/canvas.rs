@@ -0,0 +1,158 @@
+use nannou::geom::Rect;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const MM_PER_INCH: f32 = 25.4;
+
+// Trimmed size of a print, in millimeters.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PaperSize {
+  pub width: f32,
+  pub height: f32,
+}
+
+impl PaperSize {
+  pub fn new(width: f32, height: f32) -> Self {
+    Self { width, height }
+  }
+
+  pub fn landscape(self) -> Self {
+    Self::new(self.width.max(self.height), self.width.min(self.height))
+  }
+}
+
+impl fmt::Display for PaperSize {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}x{}mm", self.width, self.height)
+  }
+}
+
+// Parse ISO A sizes (a0 to a6), letter, legal, tabloid, or `<width>x<height><unit>` with a mm, cm or
+// in unit, such as `12x12in`. Named sizes are in portrait orientation.
+impl FromStr for PaperSize {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim().to_lowercase();
+    let inches =
+      |width: f32, height: f32| PaperSize::new(width * MM_PER_INCH, height * MM_PER_INCH);
+    let named = match s.as_str() {
+      "a0" => Some(PaperSize::new(841., 1189.)),
+      "a1" => Some(PaperSize::new(594., 841.)),
+      "a2" => Some(PaperSize::new(420., 594.)),
+      "a3" => Some(PaperSize::new(297., 420.)),
+      "a4" => Some(PaperSize::new(210., 297.)),
+      "a5" => Some(PaperSize::new(148., 210.)),
+      "a6" => Some(PaperSize::new(105., 148.)),
+      "letter" => Some(inches(8.5, 11.)),
+      "legal" => Some(inches(8.5, 14.)),
+      "tabloid" => Some(inches(11., 17.)),
+      _ => None,
+    };
+    if let Some(paper) = named {
+      return Ok(paper);
+    }
+    let invalid = || {
+      format!(
+        "invalid paper size '{}', expected a0 to a6, letter, legal, tabloid or <width>x<height> in mm, cm or in",
+        s
+      )
+    };
+    let (size, unit) = ["mm", "cm", "in"]
+      .iter()
+      .find_map(|unit| Some((s.strip_suffix(unit)?, *unit)))
+      .ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let [width, height] = [width, height].map(|side| side.trim().parse::<f32>());
+    let (width, height) = (
+      width.map_err(|_| invalid())?,
+      height.map_err(|_| invalid())?,
+    );
+    let mm = match unit {
+      "cm" => 10.,
+      "in" => MM_PER_INCH,
+      _ => 1.,
+    };
+    Ok(PaperSize::new(width * mm, height * mm))
+  }
+}
+
+impl From<PaperSize> for String {
+  fn from(paper: PaperSize) -> Self {
+    paper.to_string()
+  }
+}
+
+impl TryFrom<String> for PaperSize {
+  type Error = String;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
+
+// A print, from which the size of the texture is derived. Lengths are in millimeters. The bleed
+// extends the texture beyond the trimmed paper on every side, the margins keep the content away
+// from its edges.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Canvas {
+  pub paper: PaperSize,
+  pub dpi: f32,
+  #[serde(default)]
+  pub bleed: f32,
+  #[serde(default)]
+  pub margin: f32,
+  #[serde(default)]
+  pub landscape: bool,
+}
+
+impl Canvas {
+  pub fn new(paper: PaperSize, dpi: f32) -> Self {
+    Self {
+      paper,
+      dpi,
+      bleed: 0.,
+      margin: 0.,
+      landscape: false,
+    }
+  }
+
+  // A canvas whose paper is exactly a texture of the given size, printed at `dpi`.
+  pub fn from_pixels([width, height]: [u32; 2], dpi: f32) -> Self {
+    let to_mm = |side: u32| side as f32 / dpi * MM_PER_INCH;
+    Self::new(PaperSize::new(to_mm(width), to_mm(height)), dpi)
+  }
+
+  pub fn paper(&self) -> PaperSize {
+    match self.landscape {
+      true => self.paper.landscape(),
+      false => self.paper,
+    }
+  }
+
+  // Convert a length in millimeters to pixels.
+  pub fn to_pixels(&self, mm: f32) -> f32 {
+    mm / MM_PER_INCH * self.dpi
+  }
+
+  // Size of the texture covering the paper and its bleed.
+  pub fn texture_size(&self) -> [u32; 2] {
+    let paper = self.paper();
+    [paper.width, paper.height]
+      .map(|side| (self.to_pixels(side + 2. * self.bleed).round() as u32).max(1))
+  }
+
+  // The trimmed paper, in the coordinates of the texture centered on the origin.
+  pub fn trim(&self) -> Rect {
+    let paper = self.paper();
+    Rect::from_w_h(self.to_pixels(paper.width), self.to_pixels(paper.height))
+  }
+
+  // The part of the paper within the margins.
+  pub fn safe_area(&self) -> Rect {
+    self.trim().pad(self.to_pixels(self.margin))
+  }
+}
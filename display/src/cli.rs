@@ -0,0 +1,142 @@
+use crate::{
+  Canvas, EncoderOptions, FailurePolicy, GoldenOptions, OutputFormat, PaperSize, PdfOptions,
+  PlotterOptions, Progress, ProgressFormat, SavePolicy, TilingOptions, VideoFormat, WriterOptions,
+};
+use clap::Args;
+use std::time::Duration;
+
+// The arguments on the outputs of a run shared by the sketches, flattened into their own.
+#[derive(Args)]
+pub struct OutputArgs {
+  /// Formats of the saved frames, any of png, png16, exr, svg, pdf, hpgl and gcode (defaults to png)
+  #[clap(long = "format")]
+  pub formats: Vec<OutputFormat>,
+  /// Frames to save: every, every:<n>, final, frames:<n>,<n>,... or key (press S)
+  #[clap(long = "save")]
+  pub save_policy: Option<SavePolicy>,
+  /// What to do with a frame that cannot be written: abort, skip or retry:<n> before skipping
+  #[clap(long, default_value = "retry:3")]
+  pub on_failure: FailurePolicy,
+  /// Render the PNG image of the final frame, and of those saved on request, by tiles this many
+  /// times larger than the texture
+  #[clap(long)]
+  pub poster_scale: Option<f32>,
+  /// Largest side of a tile in pixels when rendering by tiles
+  #[clap(long, default_value_t = 4096)]
+  pub tile_size: u32,
+  /// Encode the frames into an animated file, gif, apng or y4m
+  #[clap(long)]
+  pub video: Option<VideoFormat>,
+  /// Frame rate of the animated file
+  #[clap(long, default_value_t = 30)]
+  pub fps: u32,
+  /// Number of times the animated file is played, 0 for forever
+  #[clap(long, default_value_t = 0)]
+  pub loops: u16,
+  /// GIF palette quantization speed, from 1 (best quality) to 30 (fastest)
+  #[clap(long, default_value_t = 10)]
+  pub palette_speed: i32,
+  /// Number of threads writing the frames (defaults to the number of cores, up to 4)
+  #[clap(long)]
+  pub writers: Option<usize>,
+  /// Number of frames kept in memory while they are written (defaults to twice the writers)
+  #[clap(long)]
+  pub write_queue: Option<usize>,
+  /// Seed of the random generator, drawn at random if not set
+  #[clap(long)]
+  pub seed: Option<u64>,
+  /// Compare the last frame of a run without preview to this golden image, failing if they differ
+  #[clap(long)]
+  pub golden: Option<std::path::PathBuf>,
+  /// Replace the golden image by the last frame instead of comparing them
+  #[clap(long, requires = "golden")]
+  pub update_golden: bool,
+  /// Largest perceptual difference (CIE76 delta E) between two pixels considered equal
+  #[clap(long, default_value_t = 2.3)]
+  pub golden_tolerance: f32,
+  /// Paper of the plotter the print is fitted on for hpgl and gcode, a4, letter, <w>x<h>mm, ...
+  /// (defaults to the paper of the print)
+  #[clap(long)]
+  pub plot_paper: Option<PaperSize>,
+  /// Command lifting the pen (defaults to PU; in hpgl and M5 in gcode)
+  #[clap(long)]
+  pub pen_up: Option<String>,
+  /// Command lowering the pen (defaults to PD; in hpgl and M3 S1000 in gcode)
+  #[clap(long)]
+  pub pen_down: Option<String>,
+  /// Speed of the pen while drawing in gcode, in millimeters per minute
+  #[clap(long, default_value_t = 3000.)]
+  pub feed_rate: f32,
+  /// Place the frame as an image in pdf pages rather than its geometry
+  #[clap(long)]
+  pub pdf_raster: bool,
+  /// Report the progress of the run as text or json lines, instead of each file being saved
+  #[clap(long)]
+  pub progress: Option<ProgressFormat>,
+  /// Shortest time between two progress reports, in seconds
+  #[clap(long, default_value_t = 1.)]
+  pub progress_interval: f32,
+}
+
+impl OutputArgs {
+  pub fn tiling_options(&self) -> Option<TilingOptions> {
+    self.poster_scale.map(|scale| TilingOptions {
+      tile_size: self.tile_size,
+      ..TilingOptions::new(scale)
+    })
+  }
+
+  pub fn writer_options(&self) -> WriterOptions {
+    let options = match self.writers {
+      Some(workers) => WriterOptions::new(workers),
+      None => WriterOptions::default(),
+    };
+    WriterOptions {
+      queue_size: self.write_queue.unwrap_or(options.queue_size),
+      ..options
+    }
+  }
+
+  pub fn golden_options(&self) -> GoldenOptions {
+    GoldenOptions {
+      tolerance: self.golden_tolerance,
+      update: self.update_golden,
+      ..GoldenOptions::default()
+    }
+  }
+
+  pub fn plotter_options(&self, canvas: Canvas) -> PlotterOptions {
+    PlotterOptions {
+      canvas: Some(canvas),
+      paper: self.plot_paper,
+      pen_up: self.pen_up.clone(),
+      pen_down: self.pen_down.clone(),
+      feed_rate: self.feed_rate,
+      ..PlotterOptions::default()
+    }
+  }
+
+  pub fn progress(&self, total: Option<u64>) -> Option<Progress> {
+    self.progress.map(|format| {
+      Progress::new(format, total)
+        .with_interval(Duration::from_secs_f32(self.progress_interval.max(0.)))
+    })
+  }
+
+  pub fn pdf_options(&self, canvas: Canvas) -> PdfOptions {
+    PdfOptions {
+      canvas: Some(canvas),
+      raster: self.pdf_raster,
+    }
+  }
+
+  pub fn encoder_options(&self) -> Option<EncoderOptions> {
+    let format = self.video?;
+    Some(EncoderOptions {
+      frame_rate: self.fps,
+      loop_count: self.loops,
+      palette_speed: self.palette_speed,
+      ..EncoderOptions::new(format)
+    })
+  }
+}
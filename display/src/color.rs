@@ -0,0 +1,54 @@
+use nannou::color::LinSrgba;
+
+// Convert a linear channel to a non-linear sRGB one, both in [0, 1].
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+  let value = value.clamp(0., 1.);
+  if value <= 0.0031308 {
+    value * 12.92
+  } else {
+    1.055 * value.powf(1. / 2.4) - 0.055
+  }
+}
+
+pub(crate) fn linear_to_srgb8(value: f32) -> u8 {
+  (linear_to_srgb(value) * 255.).round() as u8
+}
+
+pub(crate) fn linear_to_srgb16(value: f32) -> u16 {
+  (linear_to_srgb(value) * 65535.).round() as u16
+}
+
+pub(crate) fn to_srgba8(color: &LinSrgba) -> [u8; 4] {
+  [
+    linear_to_srgb8(color.red),
+    linear_to_srgb8(color.green),
+    linear_to_srgb8(color.blue),
+    (color.alpha.clamp(0., 1.) * 255.).round() as u8,
+  ]
+}
+
+// Convert a non-linear sRGB channel to a linear one, both in [0, 1].
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+  if value <= 0.04045 {
+    value / 12.92
+  } else {
+    ((value + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+// Convert a non-linear 8-bit sRGB color to CIE L*a*b*, under a D65 white point.
+pub(crate) fn srgb8_to_lab([red, green, blue]: [u8; 3]) -> [f32; 3] {
+  let [r, g, b] = [red, green, blue].map(|c| srgb_to_linear(c as f32 / 255.));
+  let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+  let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+  let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+  let f = |t: f32| {
+    if t > 216. / 24389. {
+      t.cbrt()
+    } else {
+      (24389. / 27. * t + 16.) / 116.
+    }
+  };
+  let [fx, fy, fz] = [f(x), f(y), f(z)];
+  [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
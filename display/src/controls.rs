@@ -0,0 +1,115 @@
+use nannou::event::Key;
+
+// An action of the keyboard controls of a preview window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+  TogglePause,
+  // Pause, then advance a single frame.
+  Step,
+  // Save the current frame.
+  Snapshot,
+  // Start over with a new seed.
+  Restart,
+  ToggleSignature,
+  ToggleHelp,
+}
+
+impl Command {
+  pub const ALL: [Command; 6] = [
+    Command::TogglePause,
+    Command::Step,
+    Command::Snapshot,
+    Command::Restart,
+    Command::ToggleSignature,
+    Command::ToggleHelp,
+  ];
+
+  pub fn key(&self) -> Key {
+    match self {
+      Command::TogglePause => Key::Space,
+      Command::Step => Key::Right,
+      Command::Snapshot => Key::S,
+      Command::Restart => Key::R,
+      Command::ToggleSignature => Key::G,
+      Command::ToggleHelp => Key::H,
+    }
+  }
+
+  pub fn description(&self) -> &'static str {
+    match self {
+      Command::TogglePause => "Space  pause or resume",
+      Command::Step => "Right  advance a single frame",
+      Command::Snapshot => "S      save the current frame",
+      Command::Restart => "R      restart with a new seed",
+      Command::ToggleSignature => "G      show or hide the signature",
+      Command::ToggleHelp => "H      show or hide this help",
+    }
+  }
+}
+
+// The state of the keyboard controls of a sketch. Pausing and stepping are handled here, the other
+// commands are returned to the sketch from `key_pressed`.
+pub struct Controls {
+  commands: Vec<Command>,
+  paused: bool,
+  step_requested: bool,
+  help: bool,
+}
+
+impl Controls {
+  pub fn new(commands: &[Command]) -> Self {
+    Self {
+      commands: commands.to_vec(),
+      paused: false,
+      step_requested: false,
+      help: false,
+    }
+  }
+
+  pub fn key_pressed(&mut self, key: Key) -> Option<Command> {
+    let command = *self.commands.iter().find(|command| command.key() == key)?;
+    match command {
+      Command::TogglePause => self.paused = !self.paused,
+      Command::Step => {
+        self.paused = true;
+        self.step_requested = true;
+      }
+      Command::ToggleHelp => self.help = !self.help,
+      Command::Snapshot | Command::Restart | Command::ToggleSignature => {}
+    }
+    Some(command)
+  }
+
+  // Whether the sketch should advance a frame on this update.
+  pub fn should_step(&mut self) -> bool {
+    !self.paused || std::mem::take(&mut self.step_requested)
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  // The lines to show over the preview: the commands when the help is shown, and whether the
+  // sketch is paused.
+  pub fn overlay(&self) -> Vec<String> {
+    let mut lines = Vec::new();
+    if self.paused {
+      lines.push("Paused".to_string());
+    }
+    if self.help {
+      lines.extend(
+        self
+          .commands
+          .iter()
+          .map(|command| command.description().to_string()),
+      );
+    }
+    lines
+  }
+}
+
+impl Default for Controls {
+  fn default() -> Self {
+    Self::new(&Command::ALL)
+  }
+}
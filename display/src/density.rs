@@ -0,0 +1,182 @@
+use crate::draw_list::Primitive;
+use crate::export::LinearImage;
+use nannou::color::LinSrgba;
+use nannou::geom::Point2;
+use nannou::image::Rgba;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+// How the accumulated density of a pixel is turned into its coverage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ToneMapping {
+  // Logarithm of the density relative to the densest pixel, as fractal flame renderers do.
+  #[default]
+  Log,
+  // Density relative to the densest pixel, raised to the inverse of the gamma.
+  Gamma(f32),
+  // Density scaled by the exposure, compressed with `x / (1 + x)`.
+  Reinhard(f32),
+}
+
+impl ToneMapping {
+  fn coverage(&self, density: f32, max_density: f32) -> f32 {
+    if density <= 0. {
+      return 0.;
+    }
+    match self {
+      ToneMapping::Log => density.ln_1p() / max_density.ln_1p(),
+      ToneMapping::Gamma(gamma) => (density / max_density).powf(1. / gamma),
+      ToneMapping::Reinhard(exposure) => {
+        let x = density * exposure;
+        x / (1. + x)
+      }
+    }
+  }
+}
+
+impl fmt::Display for ToneMapping {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ToneMapping::Log => write!(f, "log"),
+      ToneMapping::Gamma(gamma) => write!(f, "gamma:{}", gamma),
+      ToneMapping::Reinhard(exposure) => write!(f, "reinhard:{}", exposure),
+    }
+  }
+}
+
+// Parse tone mappings written as `log`, `gamma:<gamma>`, `reinhard` or `reinhard:<exposure>`.
+impl FromStr for ToneMapping {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      format!(
+        "invalid tone mapping '{}', expected log, gamma:<gamma> or reinhard:<exposure>",
+        s
+      )
+    };
+    let positive = |value: &str| match value.trim().parse::<f32>() {
+      Ok(value) if value > 0. => Ok(value),
+      _ => Err(invalid()),
+    };
+    match s.trim().split_once(':') {
+      None => match s.trim() {
+        "log" => Ok(ToneMapping::Log),
+        "reinhard" => Ok(ToneMapping::Reinhard(1.)),
+        _ => Err(invalid()),
+      },
+      Some(("gamma", gamma)) => positive(gamma).map(ToneMapping::Gamma),
+      Some(("reinhard", exposure)) => positive(exposure).map(ToneMapping::Reinhard),
+      Some(_) => Err(invalid()),
+    }
+  }
+}
+
+impl From<ToneMapping> for String {
+  fn from(tone_mapping: ToneMapping) -> Self {
+    tone_mapping.to_string()
+  }
+}
+
+impl TryFrom<String> for ToneMapping {
+  type Error = String;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
+
+// A floating point histogram of the points splatted on the texture. Each bin sums the linear colors
+// of its points weighted by their alpha, along with the weights themselves.
+pub struct DensityBuffer {
+  size: [u32; 2],
+  bins: Vec<[f32; 4]>,
+}
+
+impl DensityBuffer {
+  pub fn new(size: [u32; 2]) -> Self {
+    Self {
+      size,
+      bins: vec![[0.; 4]; (size[0] * size[1]) as usize],
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.bins.fill([0.; 4]);
+  }
+
+  // Add a point, in nannou coordinates, spread over the four nearest pixels.
+  pub fn splat<C: nannou::color::IntoLinSrgba<f32>>(&mut self, point: Point2, color: C) {
+    let color = color.into_lin_srgba();
+    let [w, h] = self.size;
+    let x = point.x + w as f32 / 2. - 0.5;
+    let y = h as f32 / 2. - point.y - 0.5;
+    let (left, top) = (x.floor(), y.floor());
+    let (fx, fy) = (x - left, y - top);
+    for (dx, dy, weight) in [
+      (0, 0, (1. - fx) * (1. - fy)),
+      (1, 0, fx * (1. - fy)),
+      (0, 1, (1. - fx) * fy),
+      (1, 1, fx * fy),
+    ] {
+      let (x, y) = (left as i64 + dx, top as i64 + dy);
+      if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+        continue;
+      }
+      let weight = weight * color.alpha;
+      let bin = &mut self.bins[(y * w as i64 + x) as usize];
+      bin[0] += color.red * weight;
+      bin[1] += color.green * weight;
+      bin[2] += color.blue * weight;
+      bin[3] += weight;
+    }
+  }
+
+  pub fn splat_points<C: nannou::color::IntoLinSrgba<f32> + Copy>(
+    &mut self,
+    points: &[Point2],
+    color: C,
+  ) {
+    for point in points {
+      self.splat(*point, color);
+    }
+  }
+
+  // Accumulate the primitives of a draw list: ellipses are splatted at their center, the other
+  // primitives are ignored.
+  pub fn record(&mut self, primitives: &[Primitive]) {
+    for primitive in primitives {
+      if let Primitive::Ellipse { xy, color, .. } = primitive {
+        self.splat(*xy, *color);
+      }
+    }
+  }
+
+  // Turn the histogram into an image: every pixel takes the average color of its points, covering
+  // `background` as much as the tone mapping of its density says. Without a background, the
+  // coverage is left in the alpha channel.
+  pub fn tone_map(&self, tone_mapping: ToneMapping, background: Option<LinSrgba>) -> LinearImage {
+    let [w, h] = self.size;
+    let max_density = self.bins.iter().map(|bin| bin[3]).fold(0., f32::max);
+    LinearImage::from_fn(w, h, |x, y| {
+      let [red, green, blue, density] = self.bins[(y * w + x) as usize];
+      let coverage = tone_mapping.coverage(density, max_density).clamp(0., 1.);
+      let color = match density > 0. {
+        true => [red / density, green / density, blue / density],
+        false => [0.; 3],
+      };
+      match background {
+        Some(background) => {
+          let background = [background.red, background.green, background.blue];
+          let [red, green, blue] =
+            [0, 1, 2].map(|i| background[i] + (color[i] - background[i]) * coverage);
+          Rgba([red, green, blue, 1.])
+        }
+        None => Rgba([color[0], color[1], color[2], coverage]),
+      }
+    })
+  }
+}
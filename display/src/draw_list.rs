@@ -0,0 +1,171 @@
+use nannou::color::{IntoLinSrgba, LinSrgba};
+use nannou::geom::{Point2, Rect, Vec2};
+use std::cell::{Ref, RefCell};
+
+// A single drawing instruction sent by a sketch.
+#[derive(Clone, Debug)]
+pub enum Primitive {
+  Background {
+    color: LinSrgba,
+  },
+  Ellipse {
+    xy: Point2,
+    wh: Vec2,
+    color: LinSrgba,
+  },
+  Line {
+    start: Point2,
+    end: Point2,
+    weight: f32,
+    color: LinSrgba,
+  },
+  Text {
+    text: String,
+    rect: Rect,
+    font_size: u32,
+    color: LinSrgba,
+  },
+}
+
+impl Primitive {
+  // Scale the primitive around the origin, then move it by `offset`.
+  pub fn transformed(&self, scale: f32, offset: Vec2) -> Self {
+    match self {
+      Primitive::Background { color } => Primitive::Background { color: *color },
+      Primitive::Ellipse { xy, wh, color } => Primitive::Ellipse {
+        xy: *xy * scale + offset,
+        wh: *wh * scale,
+        color: *color,
+      },
+      Primitive::Line {
+        start,
+        end,
+        weight,
+        color,
+      } => Primitive::Line {
+        start: *start * scale + offset,
+        end: *end * scale + offset,
+        weight: weight * scale,
+        color: *color,
+      },
+      Primitive::Text {
+        text,
+        rect,
+        font_size,
+        color,
+      } => Primitive::Text {
+        text: text.clone(),
+        rect: Rect::from_xy_wh(rect.xy() * scale + offset, rect.wh() * scale),
+        font_size: (*font_size as f32 * scale).round() as u32,
+        color: *color,
+      },
+    }
+  }
+
+  // Multiply the alpha of the primitive by `opacity`.
+  pub fn faded(&self, opacity: f32) -> Self {
+    let mut primitive = self.clone();
+    match &mut primitive {
+      Primitive::Background { color }
+      | Primitive::Ellipse { color, .. }
+      | Primitive::Line { color, .. }
+      | Primitive::Text { color, .. } => color.alpha *= opacity,
+    }
+    primitive
+  }
+
+  // Multiply the color of the primitive by its alpha.
+  pub fn premultiplied(&self) -> Self {
+    let mut primitive = self.clone();
+    match &mut primitive {
+      Primitive::Background { color }
+      | Primitive::Ellipse { color, .. }
+      | Primitive::Line { color, .. }
+      | Primitive::Text { color, .. } => {
+        color.red *= color.alpha;
+        color.green *= color.alpha;
+        color.blue *= color.alpha;
+      }
+    }
+    primitive
+  }
+}
+
+// Records the primitives of the current frame so that they can be replayed by any backend.
+#[derive(Default)]
+pub struct DrawList {
+  primitives: RefCell<Vec<Primitive>>,
+  // Paths recorded for the pen plotters only, in place of the lines and points drawn.
+  plot_paths: RefCell<Vec<Vec<Point2>>>,
+}
+
+impl DrawList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn reset(&self) {
+    self.primitives.borrow_mut().clear();
+    self.plot_paths.borrow_mut().clear();
+  }
+
+  pub fn background<C: IntoLinSrgba<f32>>(&self, color: C) {
+    self.push(Primitive::Background {
+      color: color.into_lin_srgba(),
+    });
+  }
+
+  pub fn ellipse<C: IntoLinSrgba<f32>>(&self, xy: Point2, wh: Vec2, color: C) {
+    self.push(Primitive::Ellipse {
+      xy,
+      wh,
+      color: color.into_lin_srgba(),
+    });
+  }
+
+  pub fn line<C: IntoLinSrgba<f32>>(&self, start: Point2, end: Point2, weight: f32, color: C) {
+    self.push(Primitive::Line {
+      start,
+      end,
+      weight,
+      color: color.into_lin_srgba(),
+    });
+  }
+
+  // Lines joining the points in order.
+  pub fn polyline<C: IntoLinSrgba<f32>>(&self, points: &[Point2], weight: f32, color: C) {
+    let color = color.into_lin_srgba();
+    for segment in points.windows(2) {
+      self.line(segment[0], segment[1], weight, color);
+    }
+  }
+
+  // Plot the points as a single path, without drawing anything. Once a path is recorded for the
+  // frame, the lines and ellipses drawn in it are left out of the plots.
+  pub fn plot_path(&self, points: &[Point2]) {
+    if !points.is_empty() {
+      self.plot_paths.borrow_mut().push(points.to_vec());
+    }
+  }
+
+  pub fn text<C: IntoLinSrgba<f32>>(&self, text: &str, rect: Rect, font_size: u32, color: C) {
+    self.push(Primitive::Text {
+      text: text.to_string(),
+      rect,
+      font_size,
+      color: color.into_lin_srgba(),
+    });
+  }
+
+  pub fn primitives(&self) -> Ref<'_, [Primitive]> {
+    Ref::map(self.primitives.borrow(), |primitives| primitives.as_slice())
+  }
+
+  pub fn plot_paths(&self) -> Ref<'_, [Vec<Point2>]> {
+    Ref::map(self.plot_paths.borrow(), |paths| paths.as_slice())
+  }
+
+  fn push(&self, primitive: Primitive) {
+    self.primitives.borrow_mut().push(primitive);
+  }
+}
@@ -0,0 +1,300 @@
+use crate::error::{Error, Result};
+use crate::writer::WriterSlot;
+use nannou::image::RgbaImage;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::JoinHandle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoFormat {
+  Gif,
+  Apng,
+  Y4m,
+}
+
+impl VideoFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      VideoFormat::Gif => "gif",
+      VideoFormat::Apng => "png",
+      VideoFormat::Y4m => "y4m",
+    }
+  }
+}
+
+impl FromStr for VideoFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "gif" => Ok(VideoFormat::Gif),
+      "apng" => Ok(VideoFormat::Apng),
+      "y4m" => Ok(VideoFormat::Y4m),
+      _ => Err(format!(
+        "unknown video format '{}', expected gif, apng or y4m",
+        s
+      )),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct EncoderOptions {
+  pub format: VideoFormat,
+  pub frame_rate: u32,
+  // Number of times the animation is played, 0 meaning forever. Ignored by Y4M streams.
+  pub loop_count: u16,
+  // NeuQuant sampling speed used to build GIF palettes, from 1 (best) to 30 (fastest).
+  pub palette_speed: i32,
+}
+
+impl EncoderOptions {
+  pub fn new(format: VideoFormat) -> Self {
+    Self {
+      format,
+      frame_rate: 30,
+      loop_count: 0,
+      palette_speed: 10,
+    }
+  }
+}
+
+// Encodes captured frames into a single animated file on a dedicated thread.
+// Frames may be captured out of order, they are written following their index. Frames that could
+// not be captured are left out of the animation. Each frame holds a writer slot until it is encoded.
+pub struct FrameEncoder {
+  sender: Sender<(u64, Result<RgbaImage>, WriterSlot)>,
+  worker: JoinHandle<Result<()>>,
+  next_index: u64,
+}
+
+impl FrameEncoder {
+  pub fn new(path: PathBuf, options: EncoderOptions) -> Self {
+    let path = path.with_extension(options.format.extension());
+    let (sender, receiver) = channel::<(u64, Result<RgbaImage>, WriterSlot)>();
+    let worker = std::thread::spawn(move || {
+      let mut sink: Option<Box<dyn Sink>> = None;
+      let mut pending = BTreeMap::new();
+      let mut next_index = 0;
+      for (index, image, slot) in receiver {
+        pending.insert(index, (image, slot));
+        while let Some((image, slot)) = pending.remove(&next_index) {
+          match image {
+            Ok(image) => {
+              if sink.is_none() {
+                sink = Some(create_sink(&path, &options, &image)?);
+              }
+              sink.as_mut().unwrap().write(&image)?;
+              slot.complete();
+            }
+            Err(err) => eprintln!(
+              "Skipping frame {} of {}, {}",
+              next_index,
+              path.display(),
+              err
+            ),
+          }
+          next_index += 1;
+        }
+      }
+      if let Some(sink) = sink {
+        sink.finish()?;
+        eprintln!("Encoded {}", path.display());
+      }
+      Ok(())
+    });
+    Self {
+      sender,
+      worker,
+      next_index: 0,
+    }
+  }
+
+  // Reserve the position of the next frame and return a function pushing it to the encoder.
+  pub fn next_frame(
+    &mut self,
+    slot: WriterSlot,
+  ) -> impl FnOnce(Result<RgbaImage>) + Send + 'static {
+    let index = self.next_index;
+    self.next_index += 1;
+    let sender = self.sender.clone();
+    // The worker is only gone once it failed, the error is reported by `finish`.
+    move |image| {
+      let _ = sender.send((index, image, slot));
+    }
+  }
+
+  // Wait for every pushed frame to be encoded and close the file.
+  pub fn finish(self) -> Result<()> {
+    drop(self.sender);
+    self.worker.join().expect("frame encoder panicked")
+  }
+}
+
+trait Sink {
+  fn write(&mut self, image: &RgbaImage) -> Result<()>;
+  fn finish(self: Box<Self>) -> Result<()>;
+}
+
+fn create_sink(path: &Path, options: &EncoderOptions, image: &RgbaImage) -> Result<Box<dyn Sink>> {
+  let (width, height) = image.dimensions();
+  Ok(match options.format {
+    VideoFormat::Gif => Box::new(GifSink::new(path, width, height, options)?),
+    VideoFormat::Apng => Box::new(ApngSink::new(path, width, height, options)?),
+    VideoFormat::Y4m => Box::new(Y4mSink::new(path, width, height, options)?),
+  })
+}
+
+struct GifSink {
+  encoder: gif::Encoder<BufWriter<File>>,
+  // Frame delay in hundredths of a second.
+  delay: u16,
+  speed: i32,
+}
+
+impl GifSink {
+  fn new(path: &Path, width: u32, height: u32, options: &EncoderOptions) -> Result<Self> {
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+      return Err(Error::VideoSize {
+        format: VideoFormat::Gif,
+        size: [width, height],
+      });
+    };
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+    let repeat = match options.loop_count {
+      0 => gif::Repeat::Infinite,
+      count => gif::Repeat::Finite(count),
+    };
+    encoder.set_repeat(repeat)?;
+    Ok(Self {
+      encoder,
+      delay: (100 / options.frame_rate.max(1)) as u16,
+      speed: options.palette_speed.clamp(1, 30),
+    })
+  }
+}
+
+impl Sink for GifSink {
+  fn write(&mut self, image: &RgbaImage) -> Result<()> {
+    let (width, height) = image.dimensions();
+    let mut pixels = image.as_raw().clone();
+    let mut frame =
+      gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, self.speed);
+    frame.delay = self.delay;
+    self.encoder.write_frame(&frame)?;
+    Ok(())
+  }
+
+  fn finish(self: Box<Self>) -> Result<()> {
+    drop(self.encoder);
+    Ok(())
+  }
+}
+
+// APNG files announce their number of frames before the first one. The frames are spooled to a
+// file next to the animation, then encoded once they are all known, whether frames were skipped or
+// the sketch restarted.
+struct ApngSink {
+  path: PathBuf,
+  spool_path: PathBuf,
+  spool: BufWriter<File>,
+  size: [u32; 2],
+  frames: u32,
+  loop_count: u16,
+  frame_rate: u16,
+}
+
+impl ApngSink {
+  fn new(path: &Path, width: u32, height: u32, options: &EncoderOptions) -> Result<Self> {
+    let spool_path = path.with_extension("frames");
+    Ok(Self {
+      path: path.to_path_buf(),
+      spool: BufWriter::new(File::create(&spool_path)?),
+      spool_path,
+      size: [width, height],
+      frames: 0,
+      loop_count: options.loop_count,
+      frame_rate: options.frame_rate.clamp(1, u16::MAX as u32) as u16,
+    })
+  }
+}
+
+impl Sink for ApngSink {
+  fn write(&mut self, image: &RgbaImage) -> Result<()> {
+    self.spool.write_all(image.as_raw())?;
+    self.frames += 1;
+    Ok(())
+  }
+
+  fn finish(self: Box<Self>) -> Result<()> {
+    let [width, height] = self.size;
+    drop(self.spool.into_inner().map_err(|err| err.into_error())?);
+    let file = BufWriter::new(File::create(&self.path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(self.frames, self.loop_count as u32)?;
+    encoder.set_frame_delay(1, self.frame_rate)?;
+    let mut writer = encoder.write_header()?;
+    let mut spool = BufReader::new(File::open(&self.spool_path)?);
+    let mut frame = vec![0; width as usize * height as usize * 4];
+    for _ in 0..self.frames {
+      spool.read_exact(&mut frame)?;
+      writer.write_image_data(&frame)?;
+    }
+    writer.finish()?;
+    std::fs::remove_file(&self.spool_path)?;
+    Ok(())
+  }
+}
+
+// Uncompressed YUV 4:4:4 stream with BT.601 limited range colors, readable by ffmpeg and most players.
+struct Y4mSink {
+  file: BufWriter<File>,
+}
+
+impl Y4mSink {
+  fn new(path: &Path, width: u32, height: u32, options: &EncoderOptions) -> Result<Self> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+      file,
+      "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+      width, height, options.frame_rate
+    )?;
+    Ok(Self { file })
+  }
+}
+
+impl Sink for Y4mSink {
+  fn write(&mut self, image: &RgbaImage) -> Result<()> {
+    let pixels = image.pixels().map(|pixel| {
+      let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.);
+      [
+        16. + 65.481 * r + 128.553 * g + 24.966 * b,
+        128. - 37.797 * r - 74.203 * g + 112. * b,
+        128. + 112. * r - 93.786 * g - 18.214 * b,
+      ]
+    });
+    let mut planes = vec![Vec::new(); 3];
+    for pixel in pixels {
+      for (plane, value) in planes.iter_mut().zip(pixel) {
+        plane.push(value.round() as u8);
+      }
+    }
+    self.file.write_all(b"FRAME\n")?;
+    for plane in &planes {
+      self.file.write_all(plane)?;
+    }
+    Ok(())
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<()> {
+    self.file.flush()?;
+    Ok(())
+  }
+}
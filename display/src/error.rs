@@ -0,0 +1,152 @@
+use crate::encoder::VideoFormat;
+use crate::policy::FailurePolicy;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Image(nannou::image::ImageError),
+  PngEncoding(png::EncodingError),
+  PngDecoding(png::DecodingError),
+  Gif(gif::EncodingError),
+  Exr(exr::error::Error),
+  // A post-processing LUT file could not be parsed.
+  Lut(String),
+  // A post-processing effect has invalid parameters.
+  Effect(String),
+  // The texture could not be read back from the GPU.
+  Capture(String),
+  // The frames are too large for the animated format.
+  VideoSize { format: VideoFormat, size: [u32; 2] },
+  // A frame could not be written at `path`.
+  Frame { path: PathBuf, source: Box<Error> },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Io(err) => write!(f, "{}", err),
+      Error::Image(err) => write!(f, "{}", err),
+      Error::PngEncoding(err) => write!(f, "png encoding failed: {}", err),
+      Error::PngDecoding(err) => write!(f, "png decoding failed: {}", err),
+      Error::Gif(err) => write!(f, "gif encoding failed: {}", err),
+      Error::Exr(err) => write!(f, "exr encoding failed: {}", err),
+      Error::Lut(reason) => write!(f, "invalid lut file {}", reason),
+      Error::Effect(reason) => write!(f, "invalid post-processing effect, {}", reason),
+      Error::Capture(reason) => write!(f, "texture capture failed: {}", reason),
+      Error::VideoSize {
+        format,
+        size: [w, h],
+      } => write!(
+        f,
+        "frames of {}x{} pixels are too large for {} files",
+        w,
+        h,
+        format.extension()
+      ),
+      Error::Frame { path, source } => write!(f, "failed to save {}: {}", path.display(), source),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io(err) => Some(err),
+      Error::Image(err) => Some(err),
+      Error::PngEncoding(err) => Some(err),
+      Error::PngDecoding(err) => Some(err),
+      Error::Gif(err) => Some(err),
+      Error::Exr(err) => Some(err),
+      Error::Lut(_) | Error::Effect(_) | Error::Capture(_) | Error::VideoSize { .. } => None,
+      Error::Frame { source, .. } => Some(source.as_ref()),
+    }
+  }
+}
+
+impl From<std::io::Error> for Error {
+  fn from(err: std::io::Error) -> Self {
+    Error::Io(err)
+  }
+}
+
+impl From<nannou::image::ImageError> for Error {
+  fn from(err: nannou::image::ImageError) -> Self {
+    Error::Image(err)
+  }
+}
+
+impl From<png::EncodingError> for Error {
+  fn from(err: png::EncodingError) -> Self {
+    Error::PngEncoding(err)
+  }
+}
+
+impl From<png::DecodingError> for Error {
+  fn from(err: png::DecodingError) -> Self {
+    Error::PngDecoding(err)
+  }
+}
+
+impl From<gif::EncodingError> for Error {
+  fn from(err: gif::EncodingError) -> Self {
+    Error::Gif(err)
+  }
+}
+
+impl From<exr::error::Error> for Error {
+  fn from(err: exr::error::Error) -> Self {
+    Error::Exr(err)
+  }
+}
+
+// Frames that could not be written, shared with the threads writing them.
+#[derive(Clone, Default)]
+pub(crate) struct Failures {
+  policy: FailurePolicy,
+  frames: Arc<Mutex<Vec<Error>>>,
+}
+
+impl Failures {
+  pub(crate) fn new(policy: FailurePolicy) -> Self {
+    Self {
+      policy,
+      frames: Default::default(),
+    }
+  }
+
+  // Write the frame at `path` following the failure policy, keeping track of it if it fails.
+  pub(crate) fn write<F: FnMut() -> Result<()>>(&self, path: &Path, write: F) {
+    if let Err(err) = self.policy.attempt(write) {
+      self.record(path, err);
+    }
+  }
+
+  pub(crate) fn record(&self, path: &Path, err: Error) {
+    let err = Error::Frame {
+      path: path.to_path_buf(),
+      source: Box::new(err),
+    };
+    if self.policy != FailurePolicy::Abort {
+      eprintln!("Skipping frame, {}", err);
+    }
+    self.frames.lock().unwrap().push(err);
+  }
+
+  // Fail with the first failed frame if the policy does not allow skipping it.
+  pub(crate) fn check(&self) -> Result<()> {
+    let mut frames = self.frames.lock().unwrap();
+    match self.policy {
+      FailurePolicy::Abort if !frames.is_empty() => Err(frames.remove(0)),
+      _ => Ok(()),
+    }
+  }
+
+  pub(crate) fn take(&self) -> Vec<Error> {
+    std::mem::take(&mut self.frames.lock().unwrap())
+  }
+}
@@ -0,0 +1,38 @@
+use crate::color::linear_to_srgb16;
+use crate::error::Result;
+use crate::metadata::{write_png, Metadata};
+use nannou::image::{ImageBuffer, Rgba};
+use std::path::Path;
+
+// Linear RGBA floating point image, read back from the texture without any quantization.
+pub type LinearImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+// Write the image as a non-linear 16-bit sRGBA PNG holding the metadata.
+pub fn save_png16(image: &LinearImage, path: &Path, metadata: &Metadata) -> Result<()> {
+  let (w, h) = image.dimensions();
+  // PNG stores 16-bit samples in big endian order.
+  let data: Vec<u8> = image
+    .pixels()
+    .flat_map(|pixel| {
+      let [red, green, blue, alpha] = pixel.0;
+      [
+        linear_to_srgb16(red),
+        linear_to_srgb16(green),
+        linear_to_srgb16(blue),
+        (alpha.clamp(0., 1.) * 65535.).round() as u16,
+      ]
+    })
+    .flat_map(u16::to_be_bytes)
+    .collect();
+  write_png(path, [w, h], png::BitDepth::Sixteen, &data, metadata)
+}
+
+// Write the image as a linear 32-bit float OpenEXR file, keeping values above 1.
+pub fn save_exr(image: &LinearImage, path: &Path) -> Result<()> {
+  let (w, h) = image.dimensions();
+  exr::prelude::write_rgba_file(path, w as usize, h as usize, |x, y| {
+    let [red, green, blue, alpha] = image.get_pixel(x as u32, y as u32).0;
+    (red, green, blue, alpha)
+  })?;
+  Ok(())
+}
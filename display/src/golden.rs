@@ -0,0 +1,141 @@
+use crate::color::srgb8_to_lab;
+use crate::error::Result;
+use crate::metadata::{save_png, Metadata};
+use nannou::image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug)]
+pub struct GoldenOptions {
+  // Largest perceptual distance between two pixels still considered equal, as a CIE76 delta E. A
+  // distance of about 2.3 is the smallest noticeable difference.
+  pub tolerance: f32,
+  // Fraction of the pixels allowed to differ beyond the tolerance, absorbing antialiasing changes.
+  pub max_mismatch: f32,
+  // Replace the golden image by the rendered one instead of comparing them.
+  pub update: bool,
+}
+
+impl Default for GoldenOptions {
+  fn default() -> Self {
+    Self {
+      tolerance: 2.3,
+      max_mismatch: 0.001,
+      update: false,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct GoldenReport {
+  pub golden: PathBuf,
+  // Pixels differing beyond the tolerance, every pixel when the sizes differ.
+  pub mismatched: u64,
+  pub total: u64,
+  pub max_distance: f32,
+  // Where the differences were drawn, if the comparison failed.
+  pub diff: Option<PathBuf>,
+  passed: bool,
+}
+
+impl GoldenReport {
+  pub fn passed(&self) -> bool {
+    self.passed
+  }
+
+  pub fn mismatch(&self) -> f32 {
+    self.mismatched as f32 / self.total.max(1) as f32
+  }
+}
+
+impl std::fmt::Display for GoldenReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(
+      f,
+      "{} pixels out of {} ({:.3}%) differ from {}, by up to {:.1}",
+      self.mismatched,
+      self.total,
+      self.mismatch() * 100.,
+      self.golden.display(),
+      self.max_distance
+    )?;
+    if let Some(diff) = &self.diff {
+      write!(f, ", see {}", diff.display())?;
+    }
+    Ok(())
+  }
+}
+
+// Compare a rendered image to the golden image at `golden`. On failure, the differing pixels are
+// drawn in red over a faded copy of the golden image, saved beside it with a `diff.png` extension.
+pub fn check_golden(
+  image: &RgbaImage,
+  golden: &Path,
+  options: &GoldenOptions,
+) -> Result<GoldenReport> {
+  let total = image.width() as u64 * image.height() as u64;
+  if options.update {
+    save_png(image, golden, &Metadata::new())?;
+    return Ok(GoldenReport {
+      golden: golden.to_path_buf(),
+      mismatched: 0,
+      total,
+      max_distance: 0.,
+      diff: None,
+      passed: true,
+    });
+  }
+  let expected = nannou::image::open(golden)?.to_rgba8();
+  if expected.dimensions() != image.dimensions() {
+    return Ok(GoldenReport {
+      golden: golden.to_path_buf(),
+      mismatched: total,
+      total,
+      max_distance: f32::INFINITY,
+      diff: None,
+      passed: false,
+    });
+  }
+
+  let mut mismatched = 0;
+  let mut max_distance: f32 = 0.;
+  let mut diff = RgbaImage::new(image.width(), image.height());
+  for ((actual, expected), diff) in image.pixels().zip(expected.pixels()).zip(diff.pixels_mut()) {
+    let distance = distance(actual, expected);
+    max_distance = max_distance.max(distance);
+    *diff = if distance > options.tolerance {
+      mismatched += 1;
+      Rgba([255, 0, 0, 255])
+    } else {
+      let [l, _, _] = srgb8_to_lab([expected[0], expected[1], expected[2]]);
+      let gray = (191. + l / 100. * 64.).round() as u8;
+      Rgba([gray, gray, gray, 255])
+    };
+  }
+
+  let passed = mismatched as f32 <= options.max_mismatch * total as f32;
+  let diff = if passed {
+    None
+  } else {
+    let path = golden.with_extension("diff.png");
+    save_png(&diff, &path, &Metadata::new())?;
+    Some(path)
+  };
+  Ok(GoldenReport {
+    golden: golden.to_path_buf(),
+    mismatched,
+    total,
+    max_distance,
+    diff,
+    passed,
+  })
+}
+
+// Perceptual distance between two pixels, composited over black so that transparency counts.
+fn distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+  let lab = |pixel: &Rgba<u8>| {
+    let alpha = pixel[3] as f32 / 255.;
+    srgb8_to_lab([0, 1, 2].map(|i| (pixel[i] as f32 * alpha).round() as u8))
+  };
+  let ([l1, a1, b1], [l2, a2, b2]) = (lab(a), lab(b));
+  ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
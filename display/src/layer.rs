@@ -0,0 +1,203 @@
+use crate::backend::Backend;
+use crate::density::{DensityBuffer, ToneMapping};
+use crate::draw_list::{DrawList, Primitive};
+use serde::{Deserialize, Serialize};
+
+// Name of the layer every `DisplayDriver` starts with, at the bottom of the stack.
+pub const DEFAULT_LAYER: &str = "default";
+
+// How the primitives of a layer are combined with what is below them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+  #[default]
+  Normal,
+  Add,
+  Multiply,
+  Screen,
+}
+
+impl BlendMode {
+  // Blend a linear color over a linear pixel, `alpha` including the coverage of the pixel.
+  pub(crate) fn blend(&self, pixel: &mut [f32; 4], color: [f32; 3], alpha: f32) {
+    for (d, s) in pixel.iter_mut().zip(color) {
+      *d = match self {
+        BlendMode::Normal => s * alpha + *d * (1. - alpha),
+        BlendMode::Add => *d + s * alpha,
+        BlendMode::Multiply => *d * (1. - alpha) + s * *d * alpha,
+        BlendMode::Screen => *d + s * (1. - *d) * alpha,
+      };
+    }
+    pixel[3] = alpha + pixel[3] * (1. - alpha);
+  }
+
+  // Whether the GPU pipeline expects the colors multiplied by their alpha, the factors of `component`
+  // having no room left for it.
+  pub(crate) fn premultiplied(&self) -> bool {
+    matches!(self, BlendMode::Multiply | BlendMode::Screen)
+  }
+
+  // The matching color blending of the GPU pipeline, for colors premultiplied when `premultiplied`.
+  pub(crate) fn component(&self) -> nannou::wgpu::BlendComponent {
+    use nannou::wgpu::{BlendComponent, BlendFactor, BlendOperation};
+    let (src_factor, dst_factor) = match self {
+      BlendMode::Normal => (BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha),
+      BlendMode::Add => (BlendFactor::SrcAlpha, BlendFactor::One),
+      BlendMode::Multiply => (BlendFactor::Dst, BlendFactor::OneMinusSrcAlpha),
+      BlendMode::Screen => (BlendFactor::OneMinusDst, BlendFactor::One),
+    };
+    BlendComponent {
+      src_factor,
+      dst_factor,
+      operation: BlendOperation::Add,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct LayerOptions {
+  #[serde(default)]
+  pub blend: BlendMode,
+  #[serde(default = "full_opacity")]
+  pub opacity: f32,
+  // Also save the layer alone, as a transparent PNG image.
+  #[serde(default)]
+  pub export: bool,
+  // Accumulate the ellipses of the layer into a density buffer, tone mapped into the layer every
+  // frame, instead of drawing them.
+  #[serde(default)]
+  pub density: Option<ToneMapping>,
+}
+
+fn full_opacity() -> f32 {
+  1.
+}
+
+impl Default for LayerOptions {
+  fn default() -> Self {
+    Self {
+      blend: BlendMode::Normal,
+      opacity: full_opacity(),
+      export: false,
+      density: None,
+    }
+  }
+}
+
+// A draw list composited with the others in the order the layers were added. Every frame, the
+// layers are replayed from the bottom to the top onto the texture, so a layer only covers what was
+// drawn below it until then.
+pub(crate) struct Layer {
+  pub(crate) name: String,
+  pub(crate) draw: DrawList,
+  pub(crate) options: LayerOptions,
+  // The layer alone over a transparent background, when it is exported.
+  pub(crate) target: Option<Box<dyn Backend>>,
+  pub(crate) density: Option<DensityBuffer>,
+}
+
+impl Layer {
+  pub(crate) fn new(name: &str, options: LayerOptions, backend: &dyn Backend) -> Self {
+    let mut layer = Self {
+      name: name.to_string(),
+      draw: DrawList::new(),
+      options,
+      target: None,
+      density: None,
+    };
+    layer.set_options(options, backend);
+    layer
+  }
+
+  pub(crate) fn set_options(&mut self, options: LayerOptions, backend: &dyn Backend) {
+    self.options = options;
+    // Density layers are exported straight from their buffer.
+    if !options.export || options.density.is_some() {
+      self.target = None;
+    } else if self.target.is_none() {
+      self.target = Some(backend.with_size(backend.size()));
+    }
+    if options.density.is_none() {
+      self.density = None;
+    } else if self.density.is_none() {
+      self.density = Some(DensityBuffer::new(backend.size()));
+    }
+  }
+
+  // Forget everything drawn on the layer.
+  pub(crate) fn restart(&mut self, backend: &dyn Backend) {
+    self.target = None;
+    self.density = None;
+    self.set_options(self.options, backend);
+  }
+
+  // The primitives of the current frame, faded by the opacity of the layer.
+  pub(crate) fn primitives(&self) -> Vec<Primitive> {
+    let primitives = self.draw.primitives();
+    match self.options.opacity {
+      opacity if opacity < 1. => primitives
+        .iter()
+        .map(|primitive| primitive.faded(opacity))
+        .collect(),
+      _ => primitives.to_vec(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use nannou::wgpu::BlendFactor;
+
+  const MODES: [BlendMode; 4] = [
+    BlendMode::Normal,
+    BlendMode::Add,
+    BlendMode::Multiply,
+    BlendMode::Screen,
+  ];
+
+  // What the GPU pipeline computes for a color channel with the factors of `component`.
+  fn gpu_blend(mode: BlendMode, dst: f32, src: f32, alpha: f32) -> f32 {
+    let src = if mode.premultiplied() {
+      src * alpha
+    } else {
+      src
+    };
+    let factor = |factor: BlendFactor| match factor {
+      BlendFactor::One => 1.,
+      BlendFactor::SrcAlpha => alpha,
+      BlendFactor::OneMinusSrcAlpha => 1. - alpha,
+      BlendFactor::Dst => dst,
+      BlendFactor::OneMinusDst => 1. - dst,
+      factor => unreachable!("no blend mode uses {:?}", factor),
+    };
+    let component = mode.component();
+    src * factor(component.src_factor) + dst * factor(component.dst_factor)
+  }
+
+  #[test]
+  fn gpu_blending_matches_the_cpu() {
+    for mode in MODES {
+      for (dst, src, alpha) in [
+        (0.2, 0.7, 0.9),
+        (0.8, 0.3, 0.5),
+        (0.5, 1., 1.),
+        (0.6, 0.4, 0.),
+      ] {
+        let mut pixel = [dst, dst, dst, 1.];
+        mode.blend(&mut pixel, [src; 3], alpha);
+        let gpu = gpu_blend(mode, dst, src, alpha);
+        assert!(
+          (pixel[0] - gpu).abs() < 1e-6,
+          "{:?} blending {} over {} at {}: {} on the CPU, {} on the GPU",
+          mode,
+          src,
+          dst,
+          alpha,
+          pixel[0],
+          gpu
+        );
+      }
+    }
+  }
+}
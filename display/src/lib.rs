@@ -1,117 +1,717 @@
-use std::borrow::Borrow;
+mod backend;
+mod canvas;
+mod cli;
+mod color;
+mod controls;
+mod density;
+mod draw_list;
+mod encoder;
+mod error;
+mod export;
+mod golden;
+mod layer;
+pub mod metadata;
+mod output;
+mod pdf;
+mod plotter;
+mod policy;
+mod post;
+mod preview;
+mod progress;
+pub mod run;
+mod svg;
+mod tiling;
+mod writer;
+
+pub use backend::{Backend, CpuBackend, GpuBackend};
+pub use canvas::{Canvas, PaperSize};
+pub use cli::OutputArgs;
+pub use controls::{Command, Controls};
+pub use density::{DensityBuffer, ToneMapping};
+pub use draw_list::{DrawList, Primitive};
+pub use encoder::{EncoderOptions, FrameEncoder, VideoFormat};
+pub use error::{Error, Result};
+pub use export::{save_exr, save_png16, LinearImage};
+pub use golden::{check_golden, GoldenOptions, GoldenReport};
+pub use layer::{BlendMode, LayerOptions, DEFAULT_LAYER};
+pub use metadata::{read_metadata, save_png, Metadata};
+pub use output::OutputFormat;
+pub use pdf::{PdfDocument, PdfOptions, PdfPage};
+pub use plotter::{PlotDocument, PlotterOptions};
+pub use policy::{FailurePolicy, SavePolicy};
+pub use post::{Effect, PostPipeline};
+pub use preview::Preview;
+pub use progress::{Progress, ProgressFormat};
+pub use svg::SvgDocument;
+pub use tiling::TilingOptions;
+pub use writer::{WriterOptions, WriterPool, WriterSlot, WriterStats};
+
+use error::Failures;
+use layer::Layer;
 
 pub struct DisplayDriver {
-  texture: nannou::wgpu::Texture,
-  texture_capturer: nannou::wgpu::TextureCapturer,
-  texture_reshaper: nannou::wgpu::TextureReshaper,
-  draw: nannou::Draw,
-  renderer: nannou::draw::Renderer,
+  backend: Box<dyn Backend>,
+  // Composited from the bottom to the top, starting with the default layer.
+  layers: Vec<Layer>,
+  formats: Vec<OutputFormat>,
+  svg: Option<SvgDocument>,
+  plot: Option<PlotDocument>,
+  plotter: PlotterOptions,
+  pdf: Option<PdfOptions>,
+  tiling: Option<TilingOptions>,
+  // Every primitive drawn since the last background with the blend mode of its layer, kept to replay
+  // the drawing over tiles.
+  history: Vec<(BlendMode, Primitive)>,
+  // The primitives drawn since the last background, drawn on a page as they are recorded when PDF
+  // pages keep the geometry.
+  pdf_geometry: Option<PdfPage>,
+  // Color of the last background drawn, the density layers are tone mapped over it.
+  background: Option<nannou::color::LinSrgba>,
+  encoder: Option<FrameEncoder>,
+  post: Option<std::sync::Arc<PostPipeline>>,
+  writer: WriterPool,
+  metadata: std::sync::Arc<Metadata>,
+  save_policy: SavePolicy,
+  // Index of the last updated frame.
+  frame: u64,
+  failures: Failures,
+  // Path of the last updated frame, if it has not been saved yet.
+  unsaved_path: Option<std::path::PathBuf>,
+  // Path of the last updated frame, if it was saved without tiling.
+  untiled_path: Option<std::path::PathBuf>,
+  // Lines shown over the preview, left out of the outputs.
+  overlay: Vec<String>,
+  // Primitives shown over the preview, left out of the outputs.
+  preview_draw: DrawList,
+  // Reports the progress of the run, in place of a line per saved file.
+  progress: Option<Progress>,
 }
 
 impl DisplayDriver {
   pub fn new(window: &nannou::window::Window, texture_size: [u32; 2]) -> Self {
-    // Retrieve the wgpu device.
-    let device = window.device();
-    // Create the custom texture.
-    let sample_count = window.msaa_samples();
-    let texture = nannou::wgpu::TextureBuilder::new()
-      .size(texture_size)
-      // The texture will be used as the RENDER_ATTACHMENT for the `Draw` render pass.
-      // It will also be SAMPLED by the `TextureCapturer` and `TextureResizer`.
-      .usage(
-        nannou::wgpu::TextureUsages::RENDER_ATTACHMENT
-          | nannou::wgpu::TextureUsages::TEXTURE_BINDING,
-      )
-      // Use nannou's default multisampling sample count.
-      .sample_count(sample_count)
-      // Use a spacious 16-bit linear sRGBA format suitable for high quality drawing.
-      .format(nannou::wgpu::TextureFormat::Rgba16Float)
-      .build(device);
-    // Create `Draw` instance and a renderer for it.
-    let draw = nannou::Draw::new();
-    let descriptor = texture.descriptor();
-    let renderer =
-      nannou::draw::RendererBuilder::new().build_from_texture_descriptor(device, descriptor);
-    // Create the texture capturer.
-    let texture_capturer = nannou::wgpu::TextureCapturer::default();
-    // Create the texture reshaper to display on screen
-    let texture_view = texture.view().build();
-    let texture_sample_type = texture.sample_type();
-    let dst_format = nannou::Frame::TEXTURE_FORMAT;
-    let texture_reshaper = nannou::wgpu::TextureReshaper::new(
-      device,
-      &texture_view,
-      sample_count,
-      texture_sample_type,
-      sample_count,
-      dst_format,
-    );
+    Self::with_backend(GpuBackend::new(window, texture_size))
+  }
+
+  // Render on the CPU, without any window nor GPU.
+  pub fn headless(texture_size: [u32; 2]) -> Self {
+    Self::with_backend(CpuBackend::new(texture_size))
+  }
+
+  // Render on the GPU without any window, or on the CPU if there is no GPU.
+  pub fn offscreen(texture_size: [u32; 2]) -> Self {
+    match GpuBackend::offscreen(texture_size) {
+      Some(backend) => Self::with_backend(backend),
+      None => {
+        eprintln!("No GPU adapter found, rendering on the CPU");
+        Self::headless(texture_size)
+      }
+    }
+  }
 
+  pub fn with_backend<B: Backend + 'static>(backend: B) -> Self {
+    let default_layer = Layer::new(DEFAULT_LAYER, LayerOptions::default(), &backend);
     Self {
-      texture,
-      draw,
-      renderer,
-      texture_capturer,
-      texture_reshaper,
+      backend: Box::new(backend),
+      layers: vec![default_layer],
+      formats: vec![OutputFormat::Png],
+      svg: None,
+      plot: None,
+      plotter: PlotterOptions::default(),
+      pdf: None,
+      tiling: None,
+      history: Vec::new(),
+      pdf_geometry: None,
+      background: None,
+      encoder: None,
+      post: None,
+      writer: WriterPool::new(WriterOptions::default()),
+      metadata: Default::default(),
+      save_policy: SavePolicy::default(),
+      frame: 0,
+      failures: Failures::default(),
+      unsaved_path: None,
+      untiled_path: None,
+      overlay: Vec::new(),
+      preview_draw: DrawList::new(),
+      progress: None,
     }
   }
 
-  pub fn draw(&self) -> &nannou::draw::Draw {
-    self.draw.borrow()
+  // Select which updates are saved, every one of them by default.
+  pub fn with_save_policy(mut self, save_policy: SavePolicy) -> Self {
+    self.save_policy = save_policy;
+    self
   }
 
-  fn create_snapshot(&mut self, window: &nannou::window::Window) -> nannou::wgpu::TextueSnapshot {
-    let device = window.device();
-    let ce_desc = nannou::wgpu::CommandEncoderDescriptor {
-      label: Some("texture renderer"),
-    };
-    let mut encoder = device.create_command_encoder(&ce_desc);
+  // Select what happens when a frame cannot be written, aborting by default.
+  pub fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+    self.failures = Failures::new(failure_policy);
+    self
+  }
+
+  // Write the frames on `options.workers` threads, keeping at most `options.queue_size` of them in
+  // memory.
+  pub fn with_writers(mut self, options: WriterOptions) -> Self {
+    self.writer = WriterPool::new(options);
+    self
+  }
+
+  // Select the formats written on `save`, PNG only by default.
+  pub fn with_formats(mut self, formats: &[OutputFormat]) -> Self {
+    if !formats.is_empty() {
+      self.formats = formats.to_vec();
+    }
+    self.svg = self
+      .formats
+      .contains(&OutputFormat::Svg)
+      .then(|| SvgDocument::new(self.backend.size()));
+    self.plot = self
+      .formats
+      .iter()
+      .any(OutputFormat::is_plot)
+      .then(|| PlotDocument::new(self.backend.size()));
+    if self.formats.contains(&OutputFormat::Pdf) {
+      self.pdf = Some(self.pdf.unwrap_or_default());
+    }
+    self
+  }
+
+  // Select how PDF pages are laid out, and keep the geometry they need. Without it, `pdf_page`
+  // places the image of the frame.
+  pub fn with_pdf(mut self, options: PdfOptions) -> Self {
+    self.pdf = Some(options);
+    self
+  }
+
+  // Select how HPGL and G-code files are laid out on the plotter paper.
+  pub fn with_plotter(mut self, options: PlotterOptions) -> Self {
+    self.plotter = options;
+    self
+  }
+
+  // Write the PNG images of the final frame and of the frames saved on request through tiled
+  // rendering, scaled beyond the texture size. The other frames are saved at the texture size.
+  pub fn with_tiling(mut self, tiling: TilingOptions) -> Self {
+    self.tiling = Some(tiling);
+    self
+  }
+
+  // Add a layer named `name` on top of the others, or change the options of an existing one. An
+  // exported layer is also saved alone at `<path>.<name>.png`, over a transparent background.
+  pub fn with_layer(mut self, name: &str, options: LayerOptions) -> Self {
+    match self.layers.iter_mut().find(|layer| layer.name == name) {
+      Some(layer) => layer.set_options(options, &*self.backend),
+      None => self.layers.push(Layer::new(name, options, &*self.backend)),
+    }
+    self
+  }
+
+  // Report the progress of the run after every update, rather than each file being saved.
+  pub fn with_progress(mut self, progress: Progress) -> Self {
+    self.progress = Some(progress);
+    self
+  }
+
+  // Process the frames before they are written or encoded. Tiled PNG images and SVG documents are
+  // written as rendered.
+  pub fn with_post_processing(mut self, pipeline: PostPipeline) -> Self {
+    self.post = (!pipeline.is_empty()).then(|| std::sync::Arc::new(pipeline));
+    self
+  }
+
+  // Embed the metadata in every PNG image saved from now on.
+  pub fn set_metadata(&mut self, metadata: Metadata) {
+    self.metadata = std::sync::Arc::new(metadata);
+  }
+
+  // Also encode every updated frame into an animated file at `path`, whatever the save policy.
+  pub fn set_encoder(&mut self, path: std::path::PathBuf, options: EncoderOptions) {
+    self.encoder = Some(FrameEncoder::new(path, options));
+  }
+
+  // The draw list of the default layer.
+  pub fn draw(&self) -> &DrawList {
+    &self.layers[0].draw
+  }
+
+  // The draw list of the layer named `name`, if it was added.
+  pub fn layer(&self, name: &str) -> Option<&DrawList> {
     self
-      .renderer
-      .render_to_texture(device, &mut encoder, &self.draw, &self.texture);
-    // Take a snapshot of the texture. The capturer will do the following
-    // 1. Resolve the texture to a non-multisampled texture if necessary.
-    // 2. Convert the format to non-linear 8-bit sRGBA ready for image storage.
-    // 3. Copy the result to a buffer ready to be mapped for reading.
-    let snapshot = self
-      .texture_capturer
-      .capture(device, &mut encoder, &self.texture);
-    // Submit the commands for our drawing and texture capture to the GPU.
-    window.queue().submit(Some(encoder.finish()));
-    snapshot
-  }
-
-  pub fn save(&mut self, window: &nannou::window::Window, path: std::path::PathBuf) {
-    // Submit a function for writing our snapshot to a PNG.
-    // NOTE: It is essential that the commands for capturing the snapshot are `submit`ted before we
-    // attempt to read the snapshot - otherwise we will read a blank texture!
-    let path = path.with_extension("png");
-    println!("Saving {}", path.display());
-    let snapshot = self.create_snapshot(window);
-    snapshot
-      .read(move |result| {
-        let image = result.expect("failed to map texture memory").to_owned();
-        image
-          .save(&path)
-          .expect("failed to save texture to png image");
-      })
-      .unwrap();
-  }
-
-  pub fn wait(&self, window: &nannou::window::Window) {
-    println!("Waiting for PNG writing to complete");
-    let device = window.device();
+      .layers
+      .iter()
+      .find(|layer| layer.name == name)
+      .map(|layer| &layer.draw)
+  }
+
+  // The density buffer of the layer named `name`, if it accumulates its points. Points can be
+  // splatted into it directly, besides the ellipses of its draw list.
+  pub fn density(&mut self, name: &str) -> Option<&mut DensityBuffer> {
     self
-      .texture_capturer
-      .await_active_snapshots(&device)
-      .unwrap();
-    println!("PNG writing completed");
+      .layers
+      .iter_mut()
+      .find(|layer| layer.name == name)
+      .and_then(|layer| layer.density.as_mut())
+  }
+
+  // Reset the draw lists of every layer.
+  pub fn reset(&self) {
+    for layer in &self.layers {
+      layer.draw.reset();
+    }
+  }
+
+  pub fn set_overlay(&mut self, overlay: Vec<String>) {
+    self.overlay = overlay;
+  }
+
+  // The draw list shown over the preview on top of the texture, left out of the outputs. Unlike the
+  // layers, it is only reset on request.
+  pub fn preview_draw(&self) -> &DrawList {
+    &self.preview_draw
+  }
+
+  // Start over from a blank texture, forgetting every frame drawn so far. The files already written
+  // and the animated file being encoded are kept.
+  pub fn restart(&mut self) -> Result<()> {
+    self.backend.wait()?;
+    self.backend = self.backend.with_size(self.backend.size());
+    for layer in &mut self.layers {
+      layer.draw.reset();
+      layer.restart(&*self.backend);
+    }
+    let size = self.backend.size();
+    if self.svg.is_some() {
+      self.svg = Some(SvgDocument::new(size));
+    }
+    if self.plot.is_some() {
+      self.plot = Some(PlotDocument::new(size));
+    }
+    self.history.clear();
+    self.pdf_geometry = None;
+    self.background = None;
+    self.frame = 0;
+    self.unsaved_path = None;
+    self.untiled_path = None;
+    if let Some(progress) = &mut self.progress {
+      progress.restart();
+    }
+    Ok(())
+  }
+
+  // Render the draw lists of the layers to the texture, then save it at `path` if the save policy
+  // asks for it. A density layer is tone mapped over the last background and covers the layers
+  // below it, it is left out of SVG documents and tiled images.
+  pub fn update(&mut self, frame: u64, path: std::path::PathBuf) -> Result<()> {
+    self.frame = frame;
+    for layer in &mut self.layers {
+      let primitives = layer.primitives();
+      let blend = layer.options.blend;
+      if let Some(Primitive::Background { color }) = primitives
+        .iter()
+        .rev()
+        .find(|primitive| matches!(primitive, Primitive::Background { .. }))
+      {
+        self.background = Some(*color);
+      }
+      if let (Some(density), Some(tone_mapping)) = (&mut layer.density, layer.options.density) {
+        density.record(&primitives);
+        let image = density.tone_map(tone_mapping, self.background);
+        self.backend.composite(&image, blend);
+        // Only the paths recorded for the plotters are plotted, not the accumulated points.
+        if let Some(plot) = &mut self.plot {
+          plot.record(&[], &layer.draw.plot_paths());
+        }
+        continue;
+      }
+      self.backend.render(&primitives, blend);
+      if let Some(target) = &mut layer.target {
+        target.render(&primitives, BlendMode::Normal);
+      }
+      if let Some(svg) = &mut self.svg {
+        svg.record(&primitives);
+      }
+      if let Some(plot) = &mut self.plot {
+        plot.record(&primitives, &layer.draw.plot_paths());
+      }
+      let background = primitives
+        .iter()
+        .rposition(|primitive| matches!(primitive, Primitive::Background { .. }));
+      if self.pdf.is_some_and(|pdf| !pdf.raster) {
+        if background.is_some() {
+          self.pdf_geometry = None;
+        }
+        let (size, options) = (self.backend.size(), self.pdf);
+        self
+          .pdf_geometry
+          .get_or_insert_with(|| blank_page(size, options))
+          .draw_primitives(&primitives[background.unwrap_or(0)..]);
+      }
+      if self.tiling.is_some() {
+        if let Some(background) = background {
+          self.history.clear();
+          self
+            .history
+            .extend(primitives[background..].iter().map(|p| (blend, p.clone())));
+        } else {
+          self
+            .history
+            .extend(primitives.into_iter().map(|p| (blend, p)));
+        }
+      }
+    }
+    if let Some(encoder) = &mut self.encoder {
+      let slot = reserve(&self.writer, &*self.backend)?;
+      let push = encoder.next_frame(slot);
+      let post = self.post.clone();
+      self
+        .backend
+        .capture(Box::new(move |image: Result<nannou::image::RgbaImage>| {
+          push(image.map(|mut image| {
+            if let Some(post) = post {
+              post.apply(&mut image, frame);
+            }
+            image
+          }))
+        }))?;
+    }
+    let result = if self.save_policy.should_save(frame) {
+      self.save_frame(path, false)
+    } else {
+      self.unsaved_path = Some(path);
+      self.untiled_path = None;
+      self.failures.check()
+    };
+    if let Some(progress) = &mut self.progress {
+      progress.frame(frame, self.writer.stats());
+    }
+    result
+  }
+
+  // Write the current content of the texture at `path`, in every selected format. The files are
+  // written on the writer threads, the ones that cannot be written fail the next update or `wait`
+  // when the failure policy aborts.
+  pub fn save(&mut self, path: std::path::PathBuf) -> Result<()> {
+    self.save_frame(path, true)
+  }
+
+  // Save the frame, rendering its PNG image by tiles only if `tiled`, as it is too slow for every
+  // frame.
+  fn save_frame(&mut self, path: std::path::PathBuf, tiled: bool) -> Result<()> {
+    self.unsaved_path = None;
+    self.untiled_path = (self.tiling.is_some() && !tiled).then(|| path.clone());
+    let backend = &mut self.backend;
+    let failures = &self.failures;
+    let metadata = &self.metadata;
+    let verbose = self.progress.is_none();
+    // Read back at most once, `None` if it failed.
+    let mut linear: Option<Option<std::sync::Arc<LinearImage>>> = None;
+    for format in &self.formats {
+      let path = path.with_extension(format.extension());
+      if verbose {
+        eprintln!("Saving {}", path.display());
+      }
+      match format {
+        OutputFormat::Png => match self.tiling.filter(|_| tiled) {
+          Some(tiling) => save_tiled_async(
+            &**backend,
+            &self.writer,
+            failures,
+            &self.history,
+            tiling,
+            path,
+            metadata,
+          )?,
+          None => {
+            let post = self.post.clone().map(|post| (post, self.frame));
+            save_png_async(&mut **backend, &self.writer, failures, path, metadata, post)?;
+          }
+        },
+        OutputFormat::Png16 | OutputFormat::Exr => {
+          let image = linear.get_or_insert_with(|| match backend.read_linear() {
+            Ok(mut image) => {
+              if let Some(post) = &self.post {
+                post.apply_linear(&mut image, self.frame);
+              }
+              Some(std::sync::Arc::new(image))
+            }
+            Err(err) => {
+              failures.record(&path, err);
+              None
+            }
+          });
+          if let Some(image) = image.clone() {
+            let slot = reserve(&self.writer, &**backend)?;
+            let (failures, metadata, format) = (failures.clone(), metadata.clone(), *format);
+            slot.write(move || {
+              failures.write(&path, || match format {
+                OutputFormat::Png16 => save_png16(&image, &path, &metadata),
+                _ => save_exr(&image, &path),
+              })
+            });
+          }
+        }
+        OutputFormat::Svg => {
+          if let Some(svg) = &self.svg {
+            let document = svg.to_svg();
+            let slot = reserve(&self.writer, &**backend)?;
+            let failures = failures.clone();
+            slot.write(move || failures.write(&path, || Ok(std::fs::write(&path, &document)?)));
+          }
+        }
+        OutputFormat::Pdf => {
+          let mut document = PdfDocument::new();
+          match page(&mut **backend, self.pdf, self.pdf_geometry.as_ref()) {
+            Ok(page) => document.push(page),
+            Err(err) => failures.record(&path, err),
+          }
+          if !document.is_empty() {
+            let slot = reserve(&self.writer, &**backend)?;
+            let failures = failures.clone();
+            slot.write(move || failures.write(&path, || document.save(&path)));
+          }
+        }
+        OutputFormat::Hpgl | OutputFormat::Gcode => {
+          if let Some(plot) = &self.plot {
+            // Optimizing the paths may take a while, leave it to the writer threads.
+            let (plot, options) = (plot.clone(), self.plotter.clone());
+            let slot = reserve(&self.writer, &**backend)?;
+            let (failures, format) = (failures.clone(), *format);
+            slot.write(move || {
+              failures.write(&path, || {
+                let document = match format {
+                  OutputFormat::Hpgl => plot.to_hpgl(&options),
+                  _ => plot.to_gcode(&options),
+                };
+                Ok(std::fs::write(&path, document)?)
+              })
+            });
+          }
+        }
+      }
+    }
+    for layer in &mut self.layers {
+      let path = path.with_extension(format!("{}.png", layer.name));
+      if let Some(target) = &mut layer.target {
+        if verbose {
+          eprintln!("Saving {}", path.display());
+        }
+        save_png_async(&mut **target, &self.writer, failures, path, metadata, None)?;
+      } else if let (Some(density), Some(tone_mapping), true) =
+        (&layer.density, layer.options.density, layer.options.export)
+      {
+        if verbose {
+          eprintln!("Saving {}", path.display());
+        }
+        // Keep the full precision of the tone mapped density.
+        let image = density.tone_map(tone_mapping, None);
+        let slot = reserve(&self.writer, &**backend)?;
+        let (failures, metadata) = (failures.clone(), metadata.clone());
+        slot.write(move || failures.write(&path, || save_png16(&image, &path, &metadata)));
+      }
+    }
+    self.failures.check()
+  }
+
+  // A page showing the current frame, captioned in its bottom margin.
+  pub fn pdf_page(&mut self, caption: Option<&str>) -> Result<PdfPage> {
+    let mut page = page(&mut *self.backend, self.pdf, self.pdf_geometry.as_ref())?;
+    if let Some(caption) = caption {
+      page.caption(caption);
+    }
+    Ok(page)
+  }
+
+  // Read the current content of the texture back as linear floating point values.
+  pub fn read_linear(&mut self) -> Result<LinearImage> {
+    self.backend.read_linear()
+  }
+
+  // Read the current content of the texture back as non-linear 8-bit sRGBA values.
+  pub fn capture(&mut self) -> Result<nannou::image::RgbaImage> {
+    tiling::capture_now(self.backend.as_mut())
+  }
+
+  // Compare the current content of the texture to the golden image at `golden`.
+  pub fn check_golden(
+    &mut self,
+    golden: &std::path::Path,
+    options: &GoldenOptions,
+  ) -> Result<GoldenReport> {
+    let image = self.capture()?;
+    golden::check_golden(&image, golden, options)
+  }
+
+  // Wait for every frame to be written. With a failure policy skipping frames, the ones that could
+  // not be written are then available from `take_failures`.
+  pub fn wait(&mut self) -> Result<()> {
+    if self.save_policy == SavePolicy::FinalFrame {
+      if let Some(path) = self.unsaved_path.take() {
+        self.save(path)?;
+      }
+    }
+    // Replace the image of the last frame by its tiled version, once the former is written.
+    if let (Some(tiling), Some(path)) = (self.tiling, self.untiled_path.take()) {
+      if self.formats.contains(&OutputFormat::Png) {
+        self.backend.wait()?;
+        self.writer.flush();
+        let path = path.with_extension(OutputFormat::Png.extension());
+        let (writer, failures) = (&self.writer, &self.failures);
+        save_tiled_async(
+          &*self.backend,
+          writer,
+          failures,
+          &self.history,
+          tiling,
+          path,
+          &self.metadata,
+        )?;
+      }
+    }
+    // Only the JSON lines are printed when a script follows the run.
+    let verbose = self.progress.as_ref().map(Progress::format) != Some(ProgressFormat::Json);
+    if verbose {
+      eprintln!("Waiting for frame writing to complete");
+    }
+    self.backend.wait()?;
+    for target in self.layers.iter().filter_map(|layer| layer.target.as_ref()) {
+      target.wait()?;
+    }
+    self.writer.flush();
+    let stats = self.writer.stats();
+    match &mut self.progress {
+      Some(progress) => progress.finish(stats),
+      None => eprintln!(
+        "Wrote {} frames in {:.1}s, {:.1} frames/s",
+        stats.written,
+        stats.elapsed.as_secs_f64(),
+        stats.frames_per_second()
+      ),
+    }
+    if let Some(encoder) = self.encoder.take() {
+      if verbose {
+        eprintln!("Waiting for frame encoding to complete");
+      }
+      encoder.finish()?;
+    }
+    self.failures.check()
+  }
+
+  pub fn writer_stats(&self) -> WriterStats {
+    self.writer.stats()
+  }
+
+  // The frames skipped so far because they could not be written.
+  pub fn take_failures(&mut self) -> Vec<Error> {
+    self.failures.take()
   }
 
   pub fn render(&self, frame: nannou::frame::Frame) {
-    let mut encoder = frame.command_encoder();
     self
-      .texture_reshaper
-      .encode_render_pass(frame.texture_view(), &mut *encoder);
+      .backend
+      .view(frame, &self.overlay, &self.preview_draw.primitives());
+  }
+}
+
+// Capture the target, then post-process the image for the given frame and write it as a PNG image
+// on the writer threads.
+fn save_png_async(
+  target: &mut dyn Backend,
+  writer: &WriterPool,
+  failures: &Failures,
+  path: std::path::PathBuf,
+  metadata: &std::sync::Arc<Metadata>,
+  post: Option<(std::sync::Arc<PostPipeline>, u64)>,
+) -> Result<()> {
+  let slot = reserve(writer, target)?;
+  let callback = {
+    let (failures, metadata, path) = (failures.clone(), metadata.clone(), path.clone());
+    Box::new(move |image: Result<_>| match image {
+      Ok(mut image) => slot.write(move || {
+        if let Some((post, frame)) = post {
+          post.apply(&mut image, frame);
+        }
+        failures.write(&path, || save_png(&image, &path, &metadata))
+      }),
+      Err(err) => failures.record(&path, err),
+    })
+  };
+  if let Err(err) = target.capture(callback) {
+    failures.record(&path, err);
+  }
+  Ok(())
+}
+
+// Render the primitives over tiles and stitch them into a PNG image on a writer thread.
+fn save_tiled_async(
+  backend: &dyn Backend,
+  writer: &WriterPool,
+  failures: &Failures,
+  history: &[(BlendMode, Primitive)],
+  tiling: TilingOptions,
+  path: std::path::PathBuf,
+  metadata: &std::sync::Arc<Metadata>,
+) -> Result<()> {
+  let slot = reserve(writer, backend)?;
+  let (tiles, size, history) = (backend.tile_factory(), backend.size(), history.to_vec());
+  let (failures, metadata) = (failures.clone(), metadata.clone());
+  slot.write(move || {
+    failures.write(&path, || {
+      tiling::save_tiled(&tiles, size, &history, &tiling, &path, &metadata)
+    })
+  });
+  Ok(())
+}
+
+// A page holding the geometry drawn since the last background, or the image of the target when the
+// options ask for it or there are none.
+fn page(
+  backend: &mut dyn Backend,
+  options: Option<PdfOptions>,
+  geometry: Option<&PdfPage>,
+) -> Result<PdfPage> {
+  match options {
+    Some(pdf) if !pdf.raster => Ok(
+      geometry
+        .cloned()
+        .unwrap_or_else(|| blank_page(backend.size(), options)),
+    ),
+    _ => {
+      let mut page = blank_page(backend.size(), options);
+      page.draw_image(&tiling::capture_now(backend)?);
+      Ok(page)
+    }
+  }
+}
+
+// A page for a texture of `size` pixels, printed at 72 DPI if the options give no canvas.
+fn blank_page(size: [u32; 2], options: Option<PdfOptions>) -> PdfPage {
+  let canvas = options
+    .and_then(|options| options.canvas)
+    .unwrap_or_else(|| Canvas::from_pixels(size, 72.));
+  PdfPage::new(canvas)
+}
+
+// Take a slot in the writer queue. When it is full, wait for the pending captures first, as they may
+// only complete while the target is polled from this thread.
+fn reserve(writer: &WriterPool, backend: &dyn Backend) -> Result<WriterSlot> {
+  match writer.try_reserve() {
+    Some(slot) => Ok(slot),
+    None => {
+      backend.wait()?;
+      Ok(writer.reserve())
+    }
   }
 }
+
+// Version of the crate, recorded in the signatures of the renders.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// The root of the project, located the same way as `nannou::app::App::project_path`.
+pub fn project_path() -> Option<std::path::PathBuf> {
+  let exe_path = std::env::current_exe().ok()?;
+  exe_path
+    .ancestors()
+    .skip(1)
+    .find(|path| path.join("Cargo.toml").exists())
+    .map(|path| path.to_path_buf())
+}
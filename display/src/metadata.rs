@@ -0,0 +1,98 @@
+use crate::error::Result;
+use nannou::image::RgbaImage;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Textual key-value pairs embedded in the saved PNG images.
+pub type Metadata = BTreeMap<String, String>;
+
+pub const AUTHOR: &str = "Author";
+pub const LICENSE: &str = "License";
+pub const SIGNATURE: &str = "Signature";
+pub const GIT_COMMIT: &str = "Git Commit";
+pub const CONFIG_DIGEST: &str = "Config Digest";
+pub const SEED: &str = "Seed";
+pub const CONFIG: &str = "Config";
+
+// Add the metadata to the PNG header, as tEXt chunks when they are plain ASCII and as UTF-8 iTXt
+// chunks otherwise.
+pub(crate) fn add_text_chunks<W: std::io::Write>(
+  encoder: &mut png::Encoder<W>,
+  metadata: &Metadata,
+) -> std::result::Result<(), png::EncodingError> {
+  for (keyword, text) in metadata {
+    if text.is_ascii() {
+      encoder.add_text_chunk(keyword.clone(), text.clone())?;
+    } else {
+      encoder.add_itxt_chunk(keyword.clone(), text.clone())?;
+    }
+  }
+  Ok(())
+}
+
+pub(crate) fn write_png(
+  path: &Path,
+  [w, h]: [u32; 2],
+  depth: png::BitDepth,
+  data: &[u8],
+  metadata: &Metadata,
+) -> Result<()> {
+  let file = BufWriter::new(File::create(path)?);
+  let mut encoder = png::Encoder::new(file, w, h);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(depth);
+  add_text_chunks(&mut encoder, metadata)?;
+  let mut writer = encoder.write_header()?;
+  writer.write_image_data(data)?;
+  writer.finish()?;
+  Ok(())
+}
+
+// Write a non-linear 8-bit sRGBA image as a PNG holding the metadata.
+pub fn save_png(image: &RgbaImage, path: &Path, metadata: &Metadata) -> Result<()> {
+  let (w, h) = image.dimensions();
+  write_png(path, [w, h], png::BitDepth::Eight, image.as_raw(), metadata)
+}
+
+// Read back every textual chunk found in the header of a PNG image.
+pub fn read_metadata(path: &Path) -> Result<Metadata> {
+  let reader = png::Decoder::new(File::open(path)?).read_info()?;
+  let info = reader.info();
+  let mut metadata = Metadata::new();
+  for chunk in &info.uncompressed_latin1_text {
+    metadata.insert(chunk.keyword.clone(), chunk.text.clone());
+  }
+  for chunk in &info.compressed_latin1_text {
+    metadata.insert(chunk.keyword.clone(), chunk.get_text()?);
+  }
+  for chunk in &info.utf8_text {
+    metadata.insert(chunk.keyword.clone(), chunk.get_text()?);
+  }
+  Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_back_the_metadata_written() {
+    let mut metadata = Metadata::new();
+    metadata.insert(AUTHOR.to_string(), "Nadou".to_string());
+    metadata.insert(SEED.to_string(), "42".to_string());
+    // Not plain ASCII, written as an iTXt chunk.
+    metadata.insert(SIGNATURE.to_string(), "abc1234 – 0123 – 42".to_string());
+    metadata.insert(
+      CONFIG.to_string(),
+      "[window]\nbackground_color = \"#fff\"\n".to_string(),
+    );
+    let path = std::env::temp_dir().join(format!("metadata-{}.png", std::process::id()));
+    let image = RgbaImage::from_pixel(3, 2, nannou::image::Rgba([10, 20, 30, 255]));
+    save_png(&image, &path, &metadata).unwrap();
+    let read = read_metadata(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.unwrap(), metadata);
+  }
+}
@@ -0,0 +1,54 @@
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+  Png,
+  // Non-linear 16-bit sRGBA PNG read back from the floating point texture.
+  Png16,
+  // Linear floating point OpenEXR read back from the floating point texture.
+  Exr,
+  Svg,
+  // A page sized to the print, holding the geometry or the image of the frame.
+  Pdf,
+  // Pen plotter instructions drawing the lines and points.
+  Hpgl,
+  Gcode,
+}
+
+impl OutputFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      OutputFormat::Png => "png",
+      OutputFormat::Png16 => "16.png",
+      OutputFormat::Exr => "exr",
+      OutputFormat::Svg => "svg",
+      OutputFormat::Pdf => "pdf",
+      OutputFormat::Hpgl => "hpgl",
+      OutputFormat::Gcode => "gcode",
+    }
+  }
+
+  pub fn is_plot(&self) -> bool {
+    matches!(self, OutputFormat::Hpgl | OutputFormat::Gcode)
+  }
+}
+
+impl FromStr for OutputFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "png" => Ok(OutputFormat::Png),
+      "png16" => Ok(OutputFormat::Png16),
+      "exr" => Ok(OutputFormat::Exr),
+      "svg" => Ok(OutputFormat::Svg),
+      "pdf" => Ok(OutputFormat::Pdf),
+      "hpgl" => Ok(OutputFormat::Hpgl),
+      "gcode" => Ok(OutputFormat::Gcode),
+      _ => Err(format!(
+        "unknown output format '{}', expected png, png16, exr, svg, pdf, hpgl or gcode",
+        s
+      )),
+    }
+  }
+}
@@ -0,0 +1,345 @@
+use crate::canvas::{Canvas, PaperSize};
+use crate::color::linear_to_srgb;
+use crate::draw_list::Primitive;
+use crate::error::Result;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use nannou::color::LinSrgba;
+use nannou::geom::Point2;
+use nannou::image::RgbaImage;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+
+// PDF points per millimeter.
+const POINTS_PER_MM: f32 = 72. / 25.4;
+// Control point distance of the Bézier curves approximating a quarter of a circle.
+const KAPPA: f32 = 0.5523;
+// Distance from the bottom of the page to the caption, in millimeters.
+const CAPTION_OFFSET: f32 = 4.;
+const CAPTION_SIZE: f32 = 7.;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PdfOptions {
+  // Print the frames were drawn for, setting the size of the pages. The texture at 72 DPI if not
+  // set.
+  pub canvas: Option<Canvas>,
+  // Place the frame as an image at the full resolution of the texture rather than its geometry.
+  // Vector pages leave out the blend modes of the layers and the density layers.
+  pub raster: bool,
+}
+
+// A page sized to the paper of a canvas, showing its trimmed part.
+#[derive(Clone)]
+pub struct PdfPage {
+  // Size of the page, in points.
+  size: [f32; 2],
+  canvas: Canvas,
+  content: String,
+  images: Vec<RgbaImage>,
+  // Opacities of the graphics states used by the content.
+  alphas: Vec<f32>,
+}
+
+impl PdfPage {
+  pub fn new(canvas: Canvas) -> Self {
+    let PaperSize { width, height } = canvas.paper();
+    Self {
+      size: [width * POINTS_PER_MM, height * POINTS_PER_MM],
+      canvas,
+      content: String::new(),
+      images: Vec::new(),
+      alphas: Vec::new(),
+    }
+  }
+
+  // Points per pixel of the texture.
+  fn scale(&self) -> f32 {
+    POINTS_PER_MM / self.canvas.to_pixels(1.)
+  }
+
+  // Convert a point from nannou's centered coordinates to the page's, the trim being the page.
+  fn to_page(&self, point: Point2) -> (f32, f32) {
+    let trim = self.canvas.trim();
+    let scale = self.scale();
+    (
+      (point.x - trim.left()) * scale,
+      (point.y - trim.bottom()) * scale,
+    )
+  }
+
+  pub fn draw_primitives<'a, I: IntoIterator<Item = &'a Primitive>>(&mut self, primitives: I) {
+    let scale = self.scale();
+    for primitive in primitives {
+      let mut content = String::new();
+      match primitive {
+        Primitive::Background { color } => {
+          let [w, h] = self.size;
+          write!(content, "{} 0 0 {:.2} {:.2} re f", fill(color), w, h).unwrap();
+        }
+        Primitive::Ellipse { xy, wh, color } => {
+          let (x, y) = self.to_page(*xy);
+          let (rx, ry) = (wh.x / 2. * scale, wh.y / 2. * scale);
+          let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+          write!(
+            content,
+            "{} {:.2} {:.2} m {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c f",
+            fill(color),
+            x + rx, y,
+            x + rx, y + ky, x + kx, y + ry, x, y + ry,
+            x - kx, y + ry, x - rx, y + ky, x - rx, y,
+            x - rx, y - ky, x - kx, y - ry, x, y - ry,
+            x + kx, y - ry, x + rx, y - ky, x + rx, y,
+          )
+          .unwrap();
+        }
+        Primitive::Line {
+          start,
+          end,
+          weight,
+          color,
+        } => {
+          let (x0, y0) = self.to_page(*start);
+          let (x1, y1) = self.to_page(*end);
+          write!(
+            content,
+            "{} {:.2} w 1 J {:.2} {:.2} m {:.2} {:.2} l S",
+            stroke(color),
+            weight * scale,
+            x0,
+            y0,
+            x1,
+            y1
+          )
+          .unwrap();
+        }
+        Primitive::Text {
+          text,
+          rect,
+          font_size,
+          color,
+        } => {
+          let size = *font_size as f32 * scale;
+          let (x, y) = self.to_page(rect.xy());
+          // Helvetica glyphs are about half as wide as they are high.
+          let width = text.chars().count() as f32 * size * 0.5;
+          write!(
+            content,
+            "{} BT /F0 {:.2} Tf {:.2} {:.2} Td ({}) Tj ET",
+            fill(color),
+            size,
+            x - width / 2.,
+            y - size / 3.,
+            escape(text)
+          )
+          .unwrap();
+        }
+      }
+      self.push(&content, primitive_color(primitive).alpha);
+    }
+  }
+
+  // Place the whole texture, bleed included, at its print size.
+  pub fn draw_image(&mut self, image: &RgbaImage) {
+    let scale = self.scale();
+    let (w, h) = image.dimensions();
+    let (x, y) = self.to_page(Point2::new(-(w as f32) / 2., -(h as f32) / 2.));
+    let content = format!(
+      "{:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do",
+      w as f32 * scale,
+      h as f32 * scale,
+      x,
+      y,
+      self.images.len()
+    );
+    self.images.push(image.clone());
+    self.push(&content, 1.);
+  }
+
+  // Write the text in the bottom margin of the page.
+  pub fn caption(&mut self, text: &str) {
+    let offset = CAPTION_OFFSET * POINTS_PER_MM;
+    let content = format!(
+      "0.3 g BT /F0 {} Tf {:.2} {:.2} Td ({}) Tj ET",
+      CAPTION_SIZE,
+      offset,
+      offset,
+      escape(text)
+    );
+    self.push(&content, 1.);
+  }
+
+  fn push(&mut self, content: &str, alpha: f32) {
+    let alpha = alpha.clamp(0., 1.);
+    if alpha >= 1. {
+      writeln!(self.content, "q {} Q", content).unwrap();
+      return;
+    }
+    let state = match self.alphas.iter().position(|a| *a == alpha) {
+      Some(state) => state,
+      None => {
+        self.alphas.push(alpha);
+        self.alphas.len() - 1
+      }
+    };
+    writeln!(self.content, "q /GS{} gs {} Q", state, content).unwrap();
+  }
+}
+
+// Pages written into a single PDF file, in order.
+#[derive(Default)]
+pub struct PdfDocument {
+  pages: Vec<PdfPage>,
+}
+
+impl PdfDocument {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push(&mut self, page: PdfPage) {
+    self.pages.push(page);
+  }
+
+  pub fn len(&self) -> usize {
+    self.pages.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pages.is_empty()
+  }
+
+  pub fn save(&self, path: &Path) -> Result<()> {
+    std::fs::write(path, self.to_bytes()?)?;
+    Ok(())
+  }
+
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    // Objects 1 to 3 are the catalog, the page tree and the font, each page is followed by its
+    // content and its images.
+    let mut objects: Vec<Vec<u8>> = vec![
+      b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+      Vec::new(),
+      b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+    let mut kids = Vec::new();
+    for page in &self.pages {
+      let id = objects.len() + 1;
+      kids.push(format!("{} 0 R", id));
+      let images: Vec<String> = (0..page.images.len())
+        .map(|index| format!("/Im{} {} 0 R", index, id + 2 + index))
+        .collect();
+      let states: Vec<String> = page
+        .alphas
+        .iter()
+        .enumerate()
+        .map(|(index, alpha)| format!("/GS{} << /ca {} /CA {} >>", index, alpha, alpha))
+        .collect();
+      objects.push(
+        format!(
+          "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Contents {} 0 R /Resources << /Font << /F0 3 0 R >> /XObject << {} >> /ExtGState << {} >> >> >>",
+          page.size[0],
+          page.size[1],
+          id + 1,
+          images.join(" "),
+          states.join(" ")
+        )
+        .into_bytes(),
+      );
+      objects.push(stream("", page.content.as_bytes())?);
+      for image in &page.images {
+        let (w, h) = image.dimensions();
+        let rgb: Vec<u8> = image
+          .pixels()
+          .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+          .collect();
+        let dictionary = format!(
+          "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8",
+          w, h
+        );
+        objects.push(stream(&dictionary, &rgb)?);
+      }
+    }
+    objects[1] = format!(
+      "<< /Type /Pages /Kids [{}] /Count {} >>",
+      kids.join(" "),
+      kids.len()
+    )
+    .into_bytes();
+
+    let mut pdf: Vec<u8> = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+      offsets.push(pdf.len());
+      writeln!(pdf, "{} 0 obj", index + 1)?;
+      pdf.extend_from_slice(object);
+      pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    writeln!(pdf, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1)?;
+    for offset in offsets {
+      writeln!(pdf, "{:010} 00000 n ", offset)?;
+    }
+    writeln!(
+      pdf,
+      "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+      objects.len() + 1,
+      xref
+    )?;
+    Ok(pdf)
+  }
+}
+
+// A compressed stream object, `dictionary` holding its entries besides the length and filter.
+fn stream(dictionary: &str, data: &[u8]) -> Result<Vec<u8>> {
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(data)?;
+  let data = encoder.finish()?;
+  let mut object = format!(
+    "<< {} /Length {} /Filter /FlateDecode >>\nstream\n",
+    dictionary,
+    data.len()
+  )
+  .into_bytes();
+  object.extend_from_slice(&data);
+  object.extend_from_slice(b"\nendstream");
+  Ok(object)
+}
+
+fn primitive_color(primitive: &Primitive) -> &LinSrgba {
+  match primitive {
+    Primitive::Background { color }
+    | Primitive::Ellipse { color, .. }
+    | Primitive::Line { color, .. }
+    | Primitive::Text { color, .. } => color,
+  }
+}
+
+fn fill(color: &LinSrgba) -> String {
+  format!("{} rg", rgb(color))
+}
+
+fn stroke(color: &LinSrgba) -> String {
+  format!("{} RG", rgb(color))
+}
+
+fn rgb(color: &LinSrgba) -> String {
+  format!(
+    "{:.3} {:.3} {:.3}",
+    linear_to_srgb(color.red),
+    linear_to_srgb(color.green),
+    linear_to_srgb(color.blue)
+  )
+}
+
+// Escape a string for a PDF literal, replacing the characters Helvetica cannot show.
+fn escape(text: &str) -> String {
+  text
+    .chars()
+    .map(|c| match c {
+      '(' | ')' | '\\' => format!("\\{}", c),
+      c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+      _ => "?".to_string(),
+    })
+    .collect()
+}
@@ -0,0 +1,321 @@
+use crate::canvas::{Canvas, PaperSize};
+use crate::draw_list::Primitive;
+use nannou::geom::Point2;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+// HPGL plotter units per millimeter.
+const HPGL_UNITS_PER_MM: f32 = 40.;
+// Resolution of the texture when no canvas is given, as in browsers.
+const DEFAULT_DPI: f32 = 96.;
+
+#[derive(Clone, Debug)]
+pub struct PlotterOptions {
+  // Print the geometry was drawn for, the texture at 96 DPI if not set.
+  pub canvas: Option<Canvas>,
+  // Paper of the plotter the trimmed canvas is fitted and centered on, the canvas paper if not set.
+  pub paper: Option<PaperSize>,
+  // Lifting and lowering the pen, `PU;` and `PD;` in HPGL and `M5` and `M3 S1000` in G-code if not
+  // set.
+  pub pen_up: Option<String>,
+  pub pen_down: Option<String>,
+  // Speed of the pen while drawing in G-code, in millimeters per minute.
+  pub feed_rate: f32,
+  // Largest gap between the ends of two paths drawn without lifting the pen, in pixels.
+  pub merge_distance: f32,
+}
+
+impl Default for PlotterOptions {
+  fn default() -> Self {
+    Self {
+      canvas: None,
+      paper: None,
+      pen_up: None,
+      pen_down: None,
+      feed_rate: 3000.,
+      merge_distance: 0.5,
+    }
+  }
+}
+
+// Accumulates the lines and points of every rendered frame as paths, to be drawn by a pen plotter.
+// Consecutive lines sharing an end are recorded as a single polyline, ellipses become dots and text
+// is left out. The paths a frame records for the plotters replace its lines and ellipses.
+#[derive(Clone)]
+pub struct PlotDocument {
+  size: [u32; 2],
+  paths: Vec<Vec<Point2>>,
+}
+
+impl PlotDocument {
+  pub fn new(size: [u32; 2]) -> Self {
+    Self {
+      size,
+      paths: Vec::new(),
+    }
+  }
+
+  pub fn record(&mut self, primitives: &[Primitive], plot_paths: &[Vec<Point2>]) {
+    let drawn = plot_paths.is_empty();
+    for primitive in primitives {
+      match primitive {
+        // The background clears the texture, anything drawn before is hidden.
+        Primitive::Background { .. } => self.paths.clear(),
+        Primitive::Line { start, end, .. } if drawn => match self.paths.last_mut() {
+          Some(path) if path.last() == Some(start) => path.push(*end),
+          _ => self.paths.push(vec![*start, *end]),
+        },
+        Primitive::Ellipse { xy, .. } if drawn => self.paths.push(vec![*xy]),
+        _ => {}
+      }
+    }
+    self.paths.extend_from_slice(plot_paths);
+  }
+
+  pub fn to_hpgl(&self, options: &PlotterOptions) -> String {
+    let pen_up = options.pen_up.as_deref().unwrap_or("PU;");
+    let pen_down = options.pen_down.as_deref().unwrap_or("PD;");
+    let units = |[x, y]: [f32; 2]| {
+      format!(
+        "{},{}",
+        (x * HPGL_UNITS_PER_MM).round() as i64,
+        (y * HPGL_UNITS_PER_MM).round() as i64
+      )
+    };
+    let mut hpgl = String::from("IN;\nSP1;\n");
+    for path in self.plot_paths(options) {
+      writeln!(hpgl, "{}\nPA{};\n{}", pen_up, units(path[0]), pen_down).unwrap();
+      if path.len() > 1 {
+        let points: Vec<String> = path[1..].iter().map(|point| units(*point)).collect();
+        writeln!(hpgl, "PA{};", points.join(",")).unwrap();
+      }
+    }
+    writeln!(hpgl, "{}\nSP0;", pen_up).unwrap();
+    hpgl
+  }
+
+  pub fn to_gcode(&self, options: &PlotterOptions) -> String {
+    let pen_up = options.pen_up.as_deref().unwrap_or("M5");
+    let pen_down = options.pen_down.as_deref().unwrap_or("M3 S1000");
+    let mut gcode = String::from("G21\nG90\n");
+    writeln!(gcode, "{}", pen_up).unwrap();
+    for path in self.plot_paths(options) {
+      let [x, y] = path[0];
+      writeln!(gcode, "G0 X{:.3} Y{:.3}\n{}", x, y, pen_down).unwrap();
+      for [x, y] in &path[1..] {
+        writeln!(gcode, "G1 X{:.3} Y{:.3} F{}", x, y, options.feed_rate).unwrap();
+      }
+      writeln!(gcode, "{}", pen_up).unwrap();
+    }
+    gcode.push_str("G0 X0 Y0\n");
+    gcode
+  }
+
+  // The paths in drawing order, in millimeters from the bottom left corner of the plotter paper.
+  // Starting from that corner, the next path is always the one with the nearest end, reversed if
+  // needed, and is drawn without lifting the pen when it starts where the previous one ended.
+  fn plot_paths(&self, options: &PlotterOptions) -> Vec<Vec<[f32; 2]>> {
+    let canvas = options
+      .canvas
+      .unwrap_or_else(|| Canvas::from_pixels(self.size, DEFAULT_DPI));
+    let trim = canvas.trim();
+    let mm_per_pixel = 1. / canvas.to_pixels(1.);
+    let [trim_w, trim_h] = [trim.w(), trim.h()].map(|side| side * mm_per_pixel);
+    let paper = options.paper.unwrap_or_else(|| canvas.paper());
+    let scale = (paper.width / trim_w).min(paper.height / trim_h);
+    let offset = [
+      (paper.width - trim_w * scale) / 2.,
+      (paper.height - trim_h * scale) / 2.,
+    ];
+    let to_paper = |point: Point2| {
+      [
+        (point.x - trim.left()) * mm_per_pixel * scale + offset[0],
+        (point.y - trim.bottom()) * mm_per_pixel * scale + offset[1],
+      ]
+    };
+
+    let mut grid = EndGrid::new(self.size[0].max(self.size[1]) as f32 / 64.);
+    for (index, path) in self.paths.iter().enumerate() {
+      grid.insert(path[0], index);
+      grid.insert(path[path.len() - 1], index);
+    }
+    let mut done = vec![false; self.paths.len()];
+    let mut plot_paths: Vec<Vec<[f32; 2]>> = Vec::new();
+    let mut pen = trim.bottom_left();
+    for _ in 0..self.paths.len() {
+      let (index, reversed, distance) = match grid.nearest(pen, &self.paths, &done) {
+        Some(nearest) => nearest,
+        None => break,
+      };
+      done[index] = true;
+      let mut path: Vec<Point2> = self.paths[index].clone();
+      if reversed {
+        path.reverse();
+      }
+      pen = path[path.len() - 1];
+      let merge = distance <= options.merge_distance && !plot_paths.is_empty();
+      let points = path.into_iter().skip(merge as usize).map(to_paper);
+      match plot_paths.last_mut() {
+        Some(plot_path) if merge => plot_path.extend(points),
+        _ => plot_paths.push(points.collect()),
+      }
+    }
+    plot_paths
+  }
+}
+
+// The ends of the paths, bucketed in square cells to find the nearest one quickly.
+struct EndGrid {
+  cell: f32,
+  cells: HashMap<(i32, i32), Vec<usize>>,
+  // Largest distance between any cell and the origin cell, in cells.
+  extent: i32,
+}
+
+impl EndGrid {
+  fn new(cell: f32) -> Self {
+    Self {
+      cell: cell.max(1.),
+      cells: HashMap::new(),
+      extent: 0,
+    }
+  }
+
+  fn key(&self, point: Point2) -> (i32, i32) {
+    (
+      (point.x / self.cell).floor() as i32,
+      (point.y / self.cell).floor() as i32,
+    )
+  }
+
+  fn insert(&mut self, point: Point2, index: usize) {
+    let key = self.key(point);
+    self.extent = self.extent.max(key.0.abs()).max(key.1.abs());
+    self.cells.entry(key).or_default().push(index);
+  }
+
+  // The path with an end nearest to `point` that is not done, whether it has to be reversed to start
+  // with that end, and the distance to it.
+  fn nearest(
+    &self,
+    point: Point2,
+    paths: &[Vec<Point2>],
+    done: &[bool],
+  ) -> Option<(usize, bool, f32)> {
+    let (cx, cy) = self.key(point);
+    let max_radius = self.extent + cx.abs().max(cy.abs()) + 1;
+    let mut best: Option<(usize, bool, f32)> = None;
+    for radius in 0..=max_radius {
+      // Every end in this ring is at least this far from the point.
+      let ring_distance = (radius - 1).max(0) as f32 * self.cell;
+      if best.is_some_and(|(_, _, distance)| distance <= ring_distance) {
+        break;
+      }
+      for dx in -radius..=radius {
+        for dy in -radius..=radius {
+          if dx.abs().max(dy.abs()) != radius {
+            continue;
+          }
+          for &index in self.cells.get(&(cx + dx, cy + dy)).into_iter().flatten() {
+            if done[index] {
+              continue;
+            }
+            let path = &paths[index];
+            for (end, reversed) in [(path[0], false), (path[path.len() - 1], true)] {
+              let distance = end.distance(point);
+              if best.is_none_or(|(_, _, best)| distance < best) {
+                best = Some((index, reversed, distance));
+              }
+            }
+          }
+        }
+      }
+    }
+    best
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use nannou::color::LinSrgba;
+  use nannou::geom::pt2;
+
+  // A texture of 200 by 100 pixels printed at a pixel per millimeter.
+  fn options(paper: Option<PaperSize>) -> PlotterOptions {
+    PlotterOptions {
+      canvas: Some(Canvas::from_pixels([200, 100], 25.4)),
+      paper,
+      ..PlotterOptions::default()
+    }
+  }
+
+  fn line(start: Point2, end: Point2) -> Primitive {
+    Primitive::Line {
+      start,
+      end,
+      weight: 1.,
+      color: LinSrgba::new(0., 0., 0., 1.),
+    }
+  }
+
+  #[test]
+  fn joins_lines_sharing_an_end() {
+    let mut document = PlotDocument::new([200, 100]);
+    document.record(&[line(pt2(0., 0.), pt2(10., 0.))], &[]);
+    document.record(
+      &[
+        Primitive::Background {
+          color: LinSrgba::new(1., 1., 1., 1.),
+        },
+        line(pt2(-100., -50.), pt2(-90., -50.)),
+        line(pt2(-90., -50.), pt2(-90., -40.)),
+      ],
+      &[],
+    );
+    assert_eq!(
+      document.plot_paths(&options(None)),
+      vec![vec![[0., 0.], [10., 0.], [10., 10.]]]
+    );
+  }
+
+  #[test]
+  fn orders_paths_by_nearest_end() {
+    let mut document = PlotDocument::new([200, 100]);
+    let far = vec![pt2(50., 0.), pt2(90., 0.)];
+    let reversed = vec![pt2(-60., -50.), pt2(-90., -50.)];
+    let continued = vec![pt2(-60., -50.), pt2(-60., -40.)];
+    // The lines drawn are left out when a frame records paths for the plotters.
+    document.record(
+      &[line(pt2(0., 0.), pt2(0., 10.))],
+      &[far, reversed, continued],
+    );
+    assert_eq!(
+      document.plot_paths(&options(None)),
+      vec![
+        vec![[10., 0.], [40., 0.], [40., 10.]],
+        vec![[150., 50.], [190., 50.]],
+      ]
+    );
+  }
+
+  #[test]
+  fn scales_paths_onto_the_plotter_paper() {
+    let mut document = PlotDocument::new([200, 100]);
+    document.record(&[line(pt2(-100., -50.), pt2(100., 50.))], &[]);
+    // Twice as large to fit the width, centered vertically.
+    let options = options(Some(PaperSize::new(400., 400.)));
+    let hpgl = document.to_hpgl(&options);
+    assert!(
+      hpgl.contains("PU;\nPA0,4000;\nPD;\nPA16000,12000;\n"),
+      "{}",
+      hpgl
+    );
+    let gcode = document.to_gcode(&options);
+    assert!(
+      gcode.contains("G0 X0.000 Y100.000\nM3 S1000\nG1 X400.000 Y300.000 F3000\nM5\n"),
+      "{}",
+      gcode
+    );
+  }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+// Which updates get their frame written to disk.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SavePolicy {
+  #[default]
+  EveryFrame,
+  EveryNthFrame(u64),
+  // Only the last frame, written when the driver is waited on.
+  FinalFrame,
+  Frames(Vec<u64>),
+  // Only the frames saved with `DisplayDriver::save`, as the snapshot key of the sketches does.
+  OnKeyPress,
+}
+
+impl SavePolicy {
+  pub fn should_save(&self, frame: u64) -> bool {
+    match self {
+      SavePolicy::EveryFrame => true,
+      SavePolicy::EveryNthFrame(n) => frame.is_multiple_of(*n),
+      SavePolicy::Frames(frames) => frames.contains(&frame),
+      SavePolicy::FinalFrame | SavePolicy::OnKeyPress => false,
+    }
+  }
+}
+
+impl fmt::Display for SavePolicy {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SavePolicy::EveryFrame => write!(f, "every"),
+      SavePolicy::EveryNthFrame(n) => write!(f, "every:{}", n),
+      SavePolicy::FinalFrame => write!(f, "final"),
+      SavePolicy::Frames(frames) => {
+        let frames: Vec<String> = frames.iter().map(u64::to_string).collect();
+        write!(f, "frames:{}", frames.join(","))
+      }
+      SavePolicy::OnKeyPress => write!(f, "key"),
+    }
+  }
+}
+
+// Parse policies written as `every`, `every:<n>`, `final`, `frames:<n>,<n>,...` or `key`.
+impl FromStr for SavePolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      format!(
+        "invalid save policy '{}', expected every, every:<n>, final, frames:<n>,... or key",
+        s
+      )
+    };
+    match s.trim().split_once(':') {
+      None => match s.trim() {
+        "every" => Ok(SavePolicy::EveryFrame),
+        "final" => Ok(SavePolicy::FinalFrame),
+        "key" => Ok(SavePolicy::OnKeyPress),
+        _ => Err(invalid()),
+      },
+      Some(("every", n)) => match n.trim().parse() {
+        Ok(0) | Err(_) => Err(invalid()),
+        Ok(n) => Ok(SavePolicy::EveryNthFrame(n)),
+      },
+      Some(("frames", frames)) => frames
+        .split(',')
+        .map(|frame| frame.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()
+        .map(SavePolicy::Frames),
+      Some(_) => Err(invalid()),
+    }
+  }
+}
+
+impl From<SavePolicy> for String {
+  fn from(save_policy: SavePolicy) -> Self {
+    save_policy.to_string()
+  }
+}
+
+impl TryFrom<String> for SavePolicy {
+  type Error = String;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
+
+// What to do when a frame cannot be written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+  // Stop at the first failed frame.
+  #[default]
+  Abort,
+  // Report the failed frame and go on with the next ones.
+  Skip,
+  // Try writing the frame again this many times, then skip it.
+  Retry(u32),
+}
+
+impl FailurePolicy {
+  pub(crate) fn attempt<F: FnMut() -> crate::Result<()>>(&self, mut write: F) -> crate::Result<()> {
+    let retries = match self {
+      FailurePolicy::Retry(retries) => *retries,
+      FailurePolicy::Abort | FailurePolicy::Skip => 0,
+    };
+    let mut result = write();
+    for _ in 0..retries {
+      if result.is_ok() {
+        break;
+      }
+      result = write();
+    }
+    result
+  }
+}
+
+// Parse policies written as `abort`, `skip` or `retry:<n>`.
+impl FromStr for FailurePolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      format!(
+        "invalid failure policy '{}', expected abort, skip or retry:<n>",
+        s
+      )
+    };
+    match s.trim().split_once(':') {
+      None => match s.trim() {
+        "abort" => Ok(FailurePolicy::Abort),
+        "skip" => Ok(FailurePolicy::Skip),
+        _ => Err(invalid()),
+      },
+      Some(("retry", n)) => n
+        .trim()
+        .parse()
+        .map(FailurePolicy::Retry)
+        .map_err(|_| invalid()),
+      Some(_) => Err(invalid()),
+    }
+  }
+}
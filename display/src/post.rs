@@ -0,0 +1,342 @@
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::error::{Error, Result};
+use crate::export::LinearImage;
+use nannou::image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// A post-processing stage, declared in the sketch configurations as a table tagged by its `kind`.
+// Grain, levels and curves work on non-linear sRGB values for 8-bit frames, and on unclamped linear
+// values for floating point ones. LUTs always work on sRGB values, vignette, blur and bloom on
+// linear ones.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Effect {
+  // Monochrome noise of the given amplitude, changing with every frame.
+  Grain {
+    amount: f32,
+    #[serde(default)]
+    seed: u64,
+  },
+  // Darken the corners. The distance to the center is 1 in the corners.
+  Vignette {
+    strength: f32,
+    #[serde(default = "default_vignette_radius")]
+    radius: f32,
+    #[serde(default = "default_vignette_softness")]
+    softness: f32,
+  },
+  // Gaussian blur, `sigma` being in pixels.
+  Blur {
+    sigma: f32,
+  },
+  // Add a blurred copy of the parts brighter than `threshold`.
+  Bloom {
+    threshold: f32,
+    sigma: f32,
+    intensity: f32,
+  },
+  // Map the `black` and `white` input levels to 0 and 1, then apply the gamma. It must be positive.
+  Levels {
+    #[serde(default)]
+    black: f32,
+    #[serde(default = "one")]
+    white: f32,
+    #[serde(default = "one")]
+    gamma: f32,
+  },
+  // Piecewise linear tone curve through `[input, output]` points.
+  Curves {
+    points: Vec<[f32; 2]>,
+  },
+  // 3D color lookup table read from an Adobe .cube file.
+  Lut {
+    path: PathBuf,
+  },
+}
+
+fn default_vignette_radius() -> f32 {
+  0.5
+}
+
+fn default_vignette_softness() -> f32 {
+  0.5
+}
+
+fn one() -> f32 {
+  1.
+}
+
+struct Stage {
+  effect: Effect,
+  lut: Option<Lut>,
+}
+
+// Effects applied in order to the frames before they are written.
+pub struct PostPipeline {
+  stages: Vec<Stage>,
+}
+
+impl PostPipeline {
+  // Build the pipeline, checking the parameters of the effects and reading the LUT files they use.
+  pub fn new(effects: &[Effect]) -> Result<Self> {
+    let stages = effects
+      .iter()
+      .map(|effect| {
+        let mut effect = effect.clone();
+        let mut lut = None;
+        match &mut effect {
+          Effect::Levels { gamma, .. } if !(*gamma > 0. && gamma.is_finite()) => {
+            let reason = format!("levels gamma must be positive, got {}", gamma);
+            return Err(Error::Effect(reason));
+          }
+          Effect::Curves { points } => {
+            if points.iter().flatten().any(|value| !value.is_finite()) {
+              let reason = "curve points must be finite numbers".to_string();
+              return Err(Error::Effect(reason));
+            }
+            points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+          }
+          Effect::Lut { path } => lut = Some(Lut::open(path)?),
+          _ => {}
+        }
+        Ok(Stage { effect, lut })
+      })
+      .collect::<Result<_>>()?;
+    Ok(Self { stages })
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.stages.is_empty()
+  }
+
+  // Process a non-linear 8-bit sRGBA frame.
+  pub fn apply(&self, image: &mut RgbaImage, frame: u64) {
+    if self.is_empty() {
+      return;
+    }
+    let mut linear = LinearImage::from_fn(image.width(), image.height(), |x, y| {
+      let [red, green, blue, alpha] = image.get_pixel(x, y).0;
+      let [red, green, blue] = [red, green, blue].map(|c| srgb_to_linear(c as f32 / 255.));
+      Rgba([red, green, blue, alpha as f32 / 255.])
+    });
+    self.process(&mut linear, frame, true);
+    for (pixel, processed) in image.pixels_mut().zip(linear.pixels()) {
+      let [red, green, blue, alpha] = processed.0;
+      let [red, green, blue] = [red, green, blue].map(|c| (linear_to_srgb(c) * 255.).round() as u8);
+      *pixel = Rgba([red, green, blue, (alpha.clamp(0., 1.) * 255.).round() as u8]);
+    }
+  }
+
+  // Process a linear floating point frame, without clamping its values to [0, 1].
+  pub fn apply_linear(&self, image: &mut LinearImage, frame: u64) {
+    self.process(image, frame, false);
+  }
+
+  // Apply the stages to a linear frame, grain, levels and curves working on sRGB values if `srgb`.
+  fn process(&self, image: &mut LinearImage, frame: u64, srgb: bool) {
+    for stage in &self.stages {
+      match &stage.effect {
+        Effect::Grain { amount, seed } => {
+          let w = image.width() as u64;
+          let seed = hash(*seed ^ frame.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+          for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let noise = hash(seed ^ (y as u64 * w + x as u64));
+            let offset = amount * ((noise >> 40) as f32 / (1u64 << 24) as f32 * 2. - 1.);
+            map_color(pixel, srgb, |c| c + offset);
+          }
+        }
+        Effect::Vignette {
+          strength,
+          radius,
+          softness,
+        } => {
+          let (w, h) = image.dimensions();
+          let [cx, cy] = [w as f32 / 2., h as f32 / 2.];
+          let corner = (cx * cx + cy * cy).sqrt();
+          for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let [dx, dy] = [x as f32 + 0.5 - cx, y as f32 + 0.5 - cy];
+            let distance = (dx * dx + dy * dy).sqrt() / corner;
+            let t = ((distance - radius) / softness.max(f32::EPSILON)).clamp(0., 1.);
+            let factor = 1. - strength * t * t * (3. - 2. * t);
+            for c in &mut pixel.0[..3] {
+              *c *= factor;
+            }
+          }
+        }
+        Effect::Blur { sigma } => gaussian_blur(image, *sigma),
+        Effect::Bloom {
+          threshold,
+          sigma,
+          intensity,
+        } => {
+          let mut bright = image.clone();
+          for pixel in bright.pixels_mut() {
+            let [red, green, blue, _] = pixel.0;
+            let luminance = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+            let keep = ((luminance - threshold) / luminance.max(f32::EPSILON)).max(0.);
+            for c in &mut pixel.0[..3] {
+              *c *= keep;
+            }
+          }
+          gaussian_blur(&mut bright, *sigma);
+          for (pixel, glow) in image.pixels_mut().zip(bright.pixels()) {
+            for (c, g) in pixel.0[..3].iter_mut().zip(&glow.0[..3]) {
+              *c += intensity * g;
+            }
+          }
+        }
+        Effect::Levels {
+          black,
+          white,
+          gamma,
+        } => {
+          let range = (white - black).max(f32::EPSILON);
+          for pixel in image.pixels_mut() {
+            map_color(pixel, srgb, |c| {
+              let c = (c - black) / range;
+              c.signum() * c.abs().powf(1. / gamma)
+            });
+          }
+        }
+        Effect::Curves { points } => {
+          for pixel in image.pixels_mut() {
+            map_color(pixel, srgb, |c| curve(points, c));
+          }
+        }
+        Effect::Lut { .. } => {
+          if let Some(lut) = &stage.lut {
+            for pixel in image.pixels_mut() {
+              let [red, green, blue, alpha] = pixel.0;
+              let [red, green, blue] = [red, green, blue].map(linear_to_srgb);
+              let [red, green, blue] = lut.sample([red, green, blue]).map(srgb_to_linear);
+              pixel.0 = [red, green, blue, alpha];
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+// Apply `f` to the color channels of a linear pixel, as non-linear sRGB values clamped to [0, 1] if
+// `srgb`, as they are otherwise.
+fn map_color<F: Fn(f32) -> f32>(pixel: &mut Rgba<f32>, srgb: bool, f: F) {
+  for c in &mut pixel.0[..3] {
+    *c = match srgb {
+      true => srgb_to_linear(f(linear_to_srgb(*c)).clamp(0., 1.)),
+      false => f(*c),
+    };
+  }
+}
+
+fn curve(points: &[[f32; 2]], value: f32) -> f32 {
+  match points {
+    [] => value,
+    [[x, y], ..] if value <= *x => *y,
+    [.., [x, y]] if value >= *x => *y,
+    _ => {
+      let i = points.partition_point(|point| point[0] <= value);
+      let ([x0, y0], [x1, y1]) = (points[i - 1], points[i]);
+      y0 + (y1 - y0) * (value - x0) / (x1 - x0).max(f32::EPSILON)
+    }
+  }
+}
+
+// SplitMix64 finalizer, enough to decorrelate neighbouring pixels.
+fn hash(mut x: u64) -> u64 {
+  x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  x ^ (x >> 31)
+}
+
+// Separable Gaussian blur of every channel, repeating the edge pixels.
+fn gaussian_blur(image: &mut LinearImage, sigma: f32) {
+  if sigma <= 0. {
+    return;
+  }
+  let radius = (sigma * 3.).ceil() as i64;
+  let weights: Vec<f32> = (-radius..=radius)
+    .map(|i| (-(i * i) as f32 / (2. * sigma * sigma)).exp())
+    .collect();
+  let total: f32 = weights.iter().sum();
+  let weights: Vec<f32> = weights.iter().map(|weight| weight / total).collect();
+  let (w, h) = image.dimensions();
+  for [dx, dy] in [[1, 0], [0, 1]] {
+    let source = image.clone();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+      let mut sum = [0.; 4];
+      for (i, weight) in (-radius..=radius).zip(&weights) {
+        let sx = (x as i64 + i * dx).clamp(0, w as i64 - 1) as u32;
+        let sy = (y as i64 + i * dy).clamp(0, h as i64 - 1) as u32;
+        for (s, c) in sum.iter_mut().zip(source.get_pixel(sx, sy).0) {
+          *s += weight * c;
+        }
+      }
+      pixel.0 = sum;
+    }
+  }
+}
+
+struct Lut {
+  size: usize,
+  // Red varies the fastest, then green, then blue.
+  table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+  fn open(path: &Path) -> Result<Self> {
+    let invalid = |reason: &str| Error::Lut(format!("{}: {}", path.display(), reason));
+    let mut size = None;
+    let mut table = Vec::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+      let line = line.trim();
+      let mut words = line.split_whitespace();
+      match words.next() {
+        None => {}
+        Some(word) if word.starts_with('#') => {}
+        Some("LUT_3D_SIZE") => {
+          let value = words.next().and_then(|word| word.parse::<usize>().ok());
+          size = Some(value.ok_or_else(|| invalid("invalid LUT_3D_SIZE"))?);
+        }
+        Some(word) if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+        Some(_) => {
+          let values: Vec<f32> = line
+            .split_whitespace()
+            .map(|word| word.parse().map_err(|_| invalid("invalid table entry")))
+            .collect::<Result<_>>()?;
+          match values[..] {
+            [red, green, blue] => table.push([red, green, blue]),
+            _ => return Err(invalid("table entries need 3 values")),
+          }
+        }
+      }
+    }
+    let size = size.ok_or_else(|| invalid("missing LUT_3D_SIZE, only 3D LUTs are supported"))?;
+    if size < 2 || table.len() != size.pow(3) {
+      return Err(invalid("the table does not match LUT_3D_SIZE"));
+    }
+    Ok(Self { size, table })
+  }
+
+  // Trilinear interpolation of the table.
+  fn sample(&self, color: [f32; 3]) -> [f32; 3] {
+    let max = (self.size - 1) as f32;
+    let position = color.map(|c| c.clamp(0., 1.) * max);
+    let low = position.map(|p| (p.floor() as usize).min(self.size - 2));
+    let t = [0, 1, 2].map(|i| position[i] - low[i] as f32);
+    let mut result = [0.; 3];
+    for corner in 0..8 {
+      let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+      let weight: f32 = (0..3)
+        .map(|i| if offset[i] == 1 { t[i] } else { 1. - t[i] })
+        .product();
+      let [r, g, b] = [0, 1, 2].map(|i| low[i] + offset[i]);
+      let entry = self.table[(b * self.size + g) * self.size + r];
+      for (value, e) in result.iter_mut().zip(entry) {
+        *value += weight * e;
+      }
+    }
+    result
+  }
+}
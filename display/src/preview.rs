@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+// How the texture is shown while rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Preview {
+  // A window whose largest side is at most this many pixels, never larger than the texture.
+  Fit(u32),
+  // A window of the texture size multiplied by this factor.
+  Scale(f32),
+  // No window, for batch renders.
+  Hidden,
+}
+
+impl Preview {
+  // Initial size of the preview window for a texture of the given size, if there is one.
+  pub fn window_size(&self, texture_size: [u32; 2]) -> Option<[u32; 2]> {
+    let scale = match *self {
+      Preview::Fit(max_side) => {
+        (max_side as f32 / texture_size[0].max(texture_size[1]) as f32).min(1.)
+      }
+      Preview::Scale(scale) => scale,
+      Preview::Hidden => return None,
+    };
+    Some(texture_size.map(|side| ((side as f32 * scale).round() as u32).max(1)))
+  }
+}
+
+// Size of `content` scaled to fit within `target` while keeping its aspect ratio.
+pub(crate) fn letterbox(content: [u32; 2], target: [u32; 2]) -> [f32; 2] {
+  let scale = (target[0] as f32 / content[0] as f32).min(target[1] as f32 / content[1] as f32);
+  content.map(|side| side as f32 * scale)
+}
+
+impl fmt::Display for Preview {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Preview::Fit(max_side) => write!(f, "{}", max_side),
+      Preview::Scale(scale) => write!(f, "scale:{}", scale),
+      Preview::Hidden => write!(f, "none"),
+    }
+  }
+}
+
+// Parse previews written as `<max side>`, `scale:<factor>` or `none`.
+impl FromStr for Preview {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      format!(
+        "invalid preview '{}', expected <max side>, scale:<factor> or none",
+        s
+      )
+    };
+    match s.trim().split_once(':') {
+      None if s.trim() == "none" => Ok(Preview::Hidden),
+      None => s.trim().parse().map(Preview::Fit).map_err(|_| invalid()),
+      Some(("scale", scale)) => match scale.trim().parse::<f32>() {
+        Ok(scale) if scale > 0. => Ok(Preview::Scale(scale)),
+        _ => Err(invalid()),
+      },
+      Some(_) => Err(invalid()),
+    }
+  }
+}
+
+impl From<Preview> for String {
+  fn from(preview: Preview) -> Self {
+    preview.to_string()
+  }
+}
+
+impl TryFrom<String> for Preview {
+  type Error = String;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
@@ -0,0 +1,210 @@
+use crate::writer::WriterStats;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Shortest time between two reports by default.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+// How the progress of a run is reported on the standard output. Other messages are written to the
+// standard error so that JSON lines can be read from the output as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressFormat {
+  // A line of text per report.
+  Text,
+  // A JSON object per line, for the scripts following the runs.
+  Json,
+}
+
+impl FromStr for ProgressFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim() {
+      "text" => Ok(ProgressFormat::Text),
+      "json" => Ok(ProgressFormat::Json),
+      _ => Err(format!(
+        "invalid progress format '{}', expected text or json",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for ProgressFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ProgressFormat::Text => write!(f, "text"),
+      ProgressFormat::Json => write!(f, "json"),
+    }
+  }
+}
+
+// Reports the frames rendered out of the total of a run, their rate, the frames waiting to be
+// written and the time left, at most every `interval`.
+pub struct Progress {
+  format: ProgressFormat,
+  // Number of frames of the run, if it is known.
+  total: Option<u64>,
+  interval: Duration,
+  started: Instant,
+  last_frame: Option<Instant>,
+  last_report: Option<Instant>,
+  // Frames rendered since the run started.
+  frames: u64,
+  // Time taken by the last frame.
+  frame_time: Duration,
+}
+
+impl Progress {
+  pub fn new(format: ProgressFormat, total: Option<u64>) -> Self {
+    Self {
+      format,
+      total,
+      interval: DEFAULT_INTERVAL,
+      started: Instant::now(),
+      last_frame: None,
+      last_report: None,
+      frames: 0,
+      frame_time: Duration::ZERO,
+    }
+  }
+
+  pub fn with_interval(mut self, interval: Duration) -> Self {
+    self.interval = interval;
+    self
+  }
+
+  pub fn format(&self) -> ProgressFormat {
+    self.format
+  }
+
+  // Start counting the frames and the time from zero again.
+  pub fn restart(&mut self) {
+    *self = Self {
+      interval: self.interval,
+      ..Self::new(self.format, self.total)
+    };
+  }
+
+  // Count the frame `frame` as rendered, reporting it if the last report is old enough or if it is
+  // the last frame of the run.
+  pub fn frame(&mut self, frame: u64, writer: WriterStats) {
+    let now = Instant::now();
+    self.frame_time = now - self.last_frame.unwrap_or(self.started);
+    self.last_frame = Some(now);
+    self.frames += 1;
+    let last = self.total.is_some_and(|total| frame + 1 >= total);
+    let due = self
+      .last_report
+      .is_none_or(|report| now - report >= self.interval);
+    if last || due {
+      self.last_report = Some(now);
+      self.print(self.report(frame + 1, writer, false));
+    }
+  }
+
+  // Report the end of the run, once every frame was written.
+  pub fn finish(&mut self, writer: WriterStats) {
+    self.print(self.report(self.frames, writer, true));
+  }
+
+  fn report(&self, iteration: u64, writer: WriterStats, done: bool) -> Report {
+    let elapsed = self.started.elapsed();
+    let frames_per_second = self.frames as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    // Extrapolated from the mean rate of the frames so far.
+    let eta = self
+      .total
+      .filter(|_| self.frames > 0)
+      .map(|total| total.saturating_sub(iteration) as f64 / frames_per_second);
+    Report {
+      done,
+      iteration,
+      total: self.total,
+      elapsed: elapsed.as_secs_f64(),
+      frames_per_second,
+      frame_time: self.frame_time.as_secs_f64() * 1000.,
+      queued: writer.pending,
+      written: writer.written,
+      eta,
+    }
+  }
+
+  fn print(&self, report: Report) {
+    match self.format {
+      ProgressFormat::Text => println!("{}", report),
+      ProgressFormat::Json => match serde_json::to_string(&report) {
+        Ok(json) => println!("{}", json),
+        Err(err) => eprintln!("Could not report the progress, {}", err),
+      },
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct Report {
+  #[serde(rename = "event", serialize_with = "event")]
+  done: bool,
+  iteration: u64,
+  total: Option<u64>,
+  // In seconds.
+  elapsed: f64,
+  #[serde(rename = "fps")]
+  frames_per_second: f64,
+  // In milliseconds.
+  #[serde(rename = "frame_ms")]
+  frame_time: f64,
+  queued: usize,
+  written: u64,
+  // In seconds.
+  eta: Option<f64>,
+}
+
+fn event<S: Serializer>(done: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str(if *done { "done" } else { "progress" })
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.done {
+      return write!(
+        f,
+        "Rendered {} frames in {}, {:.1} frames/s, {} written",
+        self.iteration,
+        duration(self.elapsed),
+        self.frames_per_second,
+        self.written
+      );
+    }
+    match self.total {
+      Some(total) => write!(
+        f,
+        "Frame {}/{} ({:.0}%)",
+        self.iteration,
+        total,
+        self.iteration as f64 * 100. / total.max(1) as f64
+      )?,
+      None => write!(f, "Frame {}", self.iteration)?,
+    }
+    write!(
+      f,
+      ", {:.1} frames/s, {:.1} ms/frame, {} queued",
+      self.frames_per_second, self.frame_time, self.queued
+    )?;
+    if let Some(eta) = self.eta {
+      write!(f, ", ETA {}", duration(eta))?;
+    }
+    Ok(())
+  }
+}
+
+// Format seconds as `1h02m03s`, `2m03s` or `3.4s`.
+fn duration(seconds: f64) -> String {
+  let whole = seconds.round() as u64;
+  match whole {
+    0..=59 => format!("{:.1}s", seconds),
+    60..=3599 => format!("{}m{:02}s", whole / 60, whole % 60),
+    _ => format!("{}h{:02}m{:02}s", whole / 3600, whole / 60 % 60, whole % 60),
+  }
+}
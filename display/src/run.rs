@@ -0,0 +1,33 @@
+use crate::{DisplayDriver, GoldenOptions};
+use std::fmt::Display;
+use std::path::Path;
+
+// Wait for every output to be written, then list the frames that were skipped.
+pub fn finish(display_driver: &mut DisplayDriver) {
+  if let Err(err) = display_driver.wait() {
+    fail(err);
+  }
+  let failures = display_driver.take_failures();
+  for failure in &failures {
+    eprintln!("Frame not written, {}", failure);
+  }
+  if !failures.is_empty() {
+    eprintln!("{} frames could not be written", failures.len());
+  }
+}
+
+// Compare the last frame to the golden image, failing the run if they differ.
+pub fn check_golden(display_driver: &mut DisplayDriver, golden: &Path, options: &GoldenOptions) {
+  match display_driver.check_golden(golden, options) {
+    Ok(_) if options.update => eprintln!("Updated golden image {}", golden.display()),
+    Ok(report) if report.passed() => eprintln!("Matched golden image, {}", report),
+    Ok(report) => fail(report),
+    Err(err) => fail(err),
+  }
+}
+
+// Report the error and exit the process.
+pub fn fail<E: Display>(err: E) -> ! {
+  eprintln!("Encountered error: {}.\nProcess exited with code 1", err);
+  std::process::exit(1);
+}
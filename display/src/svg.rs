@@ -0,0 +1,133 @@
+use crate::color::to_srgba8;
+use crate::draw_list::Primitive;
+use crate::error::Result;
+use nannou::color::LinSrgba;
+use nannou::geom::Point2;
+use std::fmt::Write as _;
+use std::path::Path;
+
+// Accumulates the primitives of every rendered frame, the same way the texture does, and serializes
+// them to an SVG document sharing the texture's size. Primitives are serialized once, as they are
+// recorded, saving a frame only copies the elements.
+pub struct SvgDocument {
+  size: [u32; 2],
+  // One element per line.
+  elements: String,
+}
+
+impl SvgDocument {
+  pub fn new(size: [u32; 2]) -> Self {
+    Self {
+      size,
+      elements: String::new(),
+    }
+  }
+
+  pub fn record(&mut self, primitives: &[Primitive]) {
+    for primitive in primitives {
+      if let Primitive::Background { .. } = primitive {
+        // The background clears the texture, anything drawn before is hidden.
+        self.elements.clear();
+      }
+      let element = self.element(primitive);
+      writeln!(self.elements, "  {}", element).unwrap();
+    }
+  }
+
+  pub fn to_svg(&self) -> String {
+    let [w, h] = self.size;
+    let mut svg = String::with_capacity(self.elements.len() + 128);
+    writeln!(
+      svg,
+      r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+      w, h, w, h
+    )
+    .unwrap();
+    svg.push_str(&self.elements);
+    svg.push_str("</svg>\n");
+    svg
+  }
+
+  pub fn save(&self, path: &Path) -> Result<()> {
+    std::fs::write(path, self.to_svg())?;
+    Ok(())
+  }
+
+  // Convert a point from nannou's centered, y-up coordinates to the texture's pixel coordinates.
+  fn to_texture(&self, point: Point2) -> (f32, f32) {
+    let [w, h] = self.size;
+    (point.x + w as f32 / 2., h as f32 / 2. - point.y)
+  }
+
+  fn element(&self, primitive: &Primitive) -> String {
+    match primitive {
+      Primitive::Background { color } => {
+        format!(
+          r#"<rect width="100%" height="100%" {}/>"#,
+          paint("fill", color)
+        )
+      }
+      Primitive::Ellipse { xy, wh, color } => {
+        let (cx, cy) = self.to_texture(*xy);
+        format!(
+          r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}/>"#,
+          cx,
+          cy,
+          wh.x / 2.,
+          wh.y / 2.,
+          paint("fill", color)
+        )
+      }
+      Primitive::Line {
+        start,
+        end,
+        weight,
+        color,
+      } => {
+        let (x1, y1) = self.to_texture(*start);
+        let (x2, y2) = self.to_texture(*end);
+        format!(
+          r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}" {}/>"#,
+          x1,
+          y1,
+          x2,
+          y2,
+          weight,
+          paint("stroke", color)
+        )
+      }
+      Primitive::Text {
+        text,
+        rect,
+        font_size,
+        color,
+      } => {
+        let (x, y) = self.to_texture(rect.xy());
+        format!(
+          r#"<text x="{}" y="{}" font-family="Noto Sans, sans-serif" font-size="{}" text-anchor="middle" dominant-baseline="middle" {}>{}</text>"#,
+          x,
+          y,
+          font_size,
+          paint("fill", color),
+          escape(text)
+        )
+      }
+    }
+  }
+}
+
+fn paint(attribute: &str, color: &LinSrgba) -> String {
+  let [red, green, blue, _] = to_srgba8(color);
+  format!(
+    r##"{}="#{:02x}{:02x}{:02x}" {}-opacity="{}""##,
+    attribute, red, green, blue, attribute, color.alpha
+  )
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
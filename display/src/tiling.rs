@@ -0,0 +1,95 @@
+use crate::backend::{Backend, TileFactory};
+use crate::draw_list::Primitive;
+use crate::error::{Error, Result};
+use crate::layer::BlendMode;
+use crate::metadata::{add_text_chunks, Metadata};
+use nannou::geom::vec2;
+use nannou::image::RgbaImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::channel;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TilingOptions {
+  // Size of the output relative to the texture.
+  pub scale: f32,
+  // Largest side of a tile, in pixels. It must fit in a single texture.
+  pub tile_size: u32,
+}
+
+impl TilingOptions {
+  pub fn new(scale: f32) -> Self {
+    Self {
+      scale,
+      tile_size: 4096,
+    }
+  }
+}
+
+// Replay `primitives`, each with the blend mode of its layer, over a grid of tiles created by
+// `tiles` for a texture of `size` pixels, and stitch them into a single PNG. Tiles are encoded one
+// row at a time so only a row of tiles is kept in memory.
+pub(crate) fn save_tiled(
+  tiles: &TileFactory,
+  size: [u32; 2],
+  primitives: &[(BlendMode, Primitive)],
+  options: &TilingOptions,
+  path: &Path,
+  metadata: &Metadata,
+) -> Result<()> {
+  let [w, h] = size.map(|side| (side as f32 * options.scale).round() as u32);
+  let tile_size = options.tile_size as usize;
+  let file = BufWriter::new(File::create(path)?);
+  let mut encoder = png::Encoder::new(file, w, h);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+  add_text_chunks(&mut encoder, metadata)?;
+  let mut writer = encoder.write_header()?;
+  let mut stream = writer.stream_writer()?;
+
+  for top in (0..h).step_by(tile_size) {
+    let tile_h = options.tile_size.min(h - top);
+    let mut rows = vec![0u8; w as usize * tile_h as usize * 4];
+    for left in (0..w).step_by(tile_size) {
+      let tile_w = options.tile_size.min(w - left);
+      // Move the center of the tile to the origin of the view.
+      let center = vec2(
+        left as f32 + tile_w as f32 / 2. - w as f32 / 2.,
+        h as f32 / 2. - (top as f32 + tile_h as f32 / 2.),
+      );
+      let mut tile = tiles([tile_w, tile_h]);
+      // Render the runs of primitives sharing a blend mode together.
+      let mut run: Vec<Primitive> = Vec::new();
+      for (index, (blend, primitive)) in primitives.iter().enumerate() {
+        run.push(primitive.transformed(options.scale, -center));
+        if primitives
+          .get(index + 1)
+          .is_none_or(|(next, _)| next != blend)
+        {
+          tile.render(&run, *blend);
+          run.clear();
+        }
+      }
+      let image = capture_now(tile.as_mut())?;
+      let row_len = tile_w as usize * 4;
+      for (y, row) in image.as_raw().chunks_exact(row_len).enumerate() {
+        let start = (y * w as usize + left as usize) * 4;
+        rows[start..start + row_len].copy_from_slice(row);
+      }
+    }
+    stream.write_all(&rows)?;
+  }
+  stream.finish()?;
+  Ok(())
+}
+
+// Capture the target, blocking until the image is available.
+pub(crate) fn capture_now(target: &mut dyn Backend) -> Result<RgbaImage> {
+  let (sender, receiver) = channel();
+  target.capture(Box::new(move |image| sender.send(image).unwrap()))?;
+  target.wait()?;
+  receiver
+    .recv()
+    .map_err(|_| Error::Capture("the tile was never captured".to_string()))?
+}
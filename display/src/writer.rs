@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WriterOptions {
+  // Number of threads writing files.
+  pub workers: usize,
+  // Largest number of frames pending at once, from their capture to the end of their writing.
+  pub queue_size: usize,
+}
+
+impl WriterOptions {
+  pub fn new(workers: usize) -> Self {
+    Self {
+      workers,
+      queue_size: workers * 2,
+    }
+  }
+}
+
+impl Default for WriterOptions {
+  fn default() -> Self {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get().min(4));
+    Self::new(workers)
+  }
+}
+
+// Counts of the frames handled by a `WriterPool` since it was created.
+#[derive(Clone, Copy, Debug)]
+pub struct WriterStats {
+  pub written: u64,
+  pub pending: usize,
+  pub elapsed: Duration,
+}
+
+impl WriterStats {
+  pub fn frames_per_second(&self) -> f64 {
+    self.written as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+  }
+}
+
+struct Shared {
+  pending: Mutex<usize>,
+  released: Condvar,
+  written: AtomicU64,
+}
+
+// Writes frames on a fixed number of threads. A slot is reserved before a frame is captured and
+// released once it is written, so a run keeps a bounded number of frames in memory whatever its
+// length. Reserving blocks while the queue is full.
+pub struct WriterPool {
+  options: WriterOptions,
+  shared: Arc<Shared>,
+  sender: Sender<Job>,
+  workers: Vec<JoinHandle<()>>,
+  started: Instant,
+}
+
+impl WriterPool {
+  pub fn new(options: WriterOptions) -> Self {
+    let options = WriterOptions {
+      workers: options.workers.max(1),
+      queue_size: options.queue_size.max(1),
+    };
+    let (sender, receiver) = channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let workers = (0..options.workers)
+      .map(|_| {
+        let receiver = receiver.clone();
+        std::thread::spawn(move || work(&receiver))
+      })
+      .collect();
+    Self {
+      options,
+      shared: Arc::new(Shared {
+        pending: Mutex::new(0),
+        released: Condvar::new(),
+        written: AtomicU64::new(0),
+      }),
+      sender,
+      workers,
+      started: Instant::now(),
+    }
+  }
+
+  // Wait for a free slot in the queue.
+  pub fn reserve(&self) -> WriterSlot {
+    let mut pending = self.shared.pending.lock().unwrap();
+    while *pending >= self.options.queue_size {
+      pending = self.shared.released.wait(pending).unwrap();
+    }
+    *pending += 1;
+    self.slot()
+  }
+
+  // Take a free slot in the queue, if there is one.
+  pub fn try_reserve(&self) -> Option<WriterSlot> {
+    let mut pending = self.shared.pending.lock().unwrap();
+    if *pending >= self.options.queue_size {
+      return None;
+    }
+    *pending += 1;
+    Some(self.slot())
+  }
+
+  fn slot(&self) -> WriterSlot {
+    WriterSlot {
+      shared: self.shared.clone(),
+      sender: self.sender.clone(),
+    }
+  }
+
+  pub fn stats(&self) -> WriterStats {
+    WriterStats {
+      written: self.shared.written.load(Ordering::Relaxed),
+      pending: *self.shared.pending.lock().unwrap(),
+      elapsed: self.started.elapsed(),
+    }
+  }
+
+  // Block until every reserved slot is released.
+  pub fn flush(&self) {
+    let mut pending = self.shared.pending.lock().unwrap();
+    while *pending > 0 {
+      pending = self.shared.released.wait(pending).unwrap();
+    }
+  }
+
+  // Write the remaining frames and stop the threads.
+  pub fn finish(self) -> WriterStats {
+    self.flush();
+    let stats = self.stats();
+    drop(self.sender);
+    for worker in self.workers {
+      worker.join().expect("frame writer panicked");
+    }
+    stats
+  }
+}
+
+fn work(receiver: &Mutex<Receiver<Job>>) {
+  loop {
+    // Only hold the lock while waiting for a job, so the other threads can pick the next ones.
+    let job = receiver.lock().unwrap().recv();
+    match job {
+      Ok(job) => job(),
+      Err(_) => return,
+    }
+  }
+}
+
+// A place in the queue of a `WriterPool`, released when dropped.
+pub struct WriterSlot {
+  shared: Arc<Shared>,
+  sender: Sender<Job>,
+}
+
+impl WriterSlot {
+  // Run `write` on one of the threads of the pool, then release the slot.
+  pub fn write<F: FnOnce() + Send + 'static>(self, write: F) {
+    let sender = self.sender.clone();
+    let job = move || {
+      write();
+      self.complete();
+    };
+    // The threads only stop once every slot is released, this one included.
+    sender.send(Box::new(job)).unwrap();
+  }
+
+  // Count the frame as written and release the slot.
+  pub fn complete(self) {
+    self.shared.written.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+impl Drop for WriterSlot {
+  fn drop(&mut self) {
+    *self.shared.pending.lock().unwrap() -= 1;
+    self.shared.released.notify_all();
+  }
+}
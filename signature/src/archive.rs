@@ -0,0 +1,47 @@
+use crate::error::{Error, Result};
+use std::path::PathBuf;
+
+// Configs kept under the digest found in the signatures of their renders, so the one a render was
+// made from can still be found once the file it was read from changed.
+pub struct ConfigArchive {
+  directory: PathBuf,
+}
+
+impl ConfigArchive {
+  pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+    Self {
+      directory: directory.into(),
+    }
+  }
+
+  // Where the config with the digest `digest` is kept.
+  pub fn path(&self, digest: &str) -> PathBuf {
+    self.directory.join(digest).with_extension("toml")
+  }
+
+  // Keep `config` in the archive under `digest` if there is none there yet.
+  pub fn store(&self, digest: &str, config: &[u8]) -> Result<()> {
+    let path = self.path(digest);
+    if !path.exists() {
+      std::fs::create_dir_all(&self.directory)?;
+      // Runs storing the same config at once each write their own file, then move it in place.
+      let partial = path.with_extension(format!("{}.partial", std::process::id()));
+      std::fs::write(&partial, config)?;
+      std::fs::rename(&partial, &path)?;
+    }
+    Ok(())
+  }
+
+  // The path of the config with the digest `digest`, if it was archived.
+  pub fn lookup(&self, digest: &str) -> Option<PathBuf> {
+    Some(self.path(digest)).filter(|path| path.is_file())
+  }
+
+  // The content of the config with the digest `digest`.
+  pub fn read(&self, digest: &str) -> Result<String> {
+    match self.lookup(digest) {
+      Some(path) => Ok(std::fs::read_to_string(path)?),
+      None => Err(Error::MissingConfig(digest.to_string())),
+    }
+  }
+}
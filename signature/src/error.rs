@@ -0,0 +1,64 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+  TomlSerialization(toml::ser::Error),
+  TomlDeserialization(toml::de::Error),
+  // A signature could not be read from a string.
+  Parse(String),
+  // No config with this digest was archived.
+  MissingConfig(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Io(err) => write!(f, "{}", err),
+      Error::Json(err) => write!(f, "invalid json manifest: {}", err),
+      Error::TomlSerialization(err) => write!(f, "toml serialization failed: {}", err),
+      Error::TomlDeserialization(err) => write!(f, "invalid toml manifest: {}", err),
+      Error::Parse(reason) => write!(f, "{}", reason),
+      Error::MissingConfig(digest) => write!(f, "no config archived with digest {}", digest),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io(err) => Some(err),
+      Error::Json(err) => Some(err),
+      Error::TomlSerialization(err) => Some(err),
+      Error::TomlDeserialization(err) => Some(err),
+      Error::Parse(_) | Error::MissingConfig(_) => None,
+    }
+  }
+}
+
+impl From<std::io::Error> for Error {
+  fn from(err: std::io::Error) -> Self {
+    Error::Io(err)
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(err: serde_json::Error) -> Self {
+    Error::Json(err)
+  }
+}
+
+impl From<toml::ser::Error> for Error {
+  fn from(err: toml::ser::Error) -> Self {
+    Error::TomlSerialization(err)
+  }
+}
+
+impl From<toml::de::Error> for Error {
+  fn from(err: toml::de::Error) -> Self {
+    Error::TomlDeserialization(err)
+  }
+}
@@ -1,18 +1,216 @@
+mod archive;
+mod error;
+
+pub use archive::ConfigArchive;
+pub use error::{Error, Result};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+// Appended to the commit in the names of the renders made from a workspace with uncommitted changes.
+const DIRTY_SUFFIX: &str = "-dirty";
+// Changes to the sketch configurations do not make the workspace dirty, the config digest and the
+// archive already keep track of them.
+const IGNORED_CHANGES: &str = ":(top,exclude)configs";
+
+// What a render was made from: the code, the config and the seed, enough to make it again.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Signature {
-  hashes: Vec<String>,
+  // Name of the sketch binary.
+  pub sketch: String,
+  // Short id of the last commit, empty if it could not be read.
+  pub git_commit: String,
+  // Whether the workspace had uncommitted changes to tracked files.
+  pub dirty: bool,
+  // SHA-256 of the uncommitted changes, when there were some.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub diff_digest: Option<String>,
+  // SHA-256 of the config, as the sketch resolved it.
+  pub config_digest: String,
+  pub seed: u64,
+  // Versions of the crates the sketch was built with, by name.
+  pub crate_versions: BTreeMap<String, String>,
+  // Seconds since the Unix epoch when the render started.
+  pub timestamp: u64,
 }
 
 impl Signature {
-  pub fn new(hashes: Vec<String>) -> Self {
-    Self { hashes }
+  // Sign a render of `sketch` starting now, from the current commit.
+  pub fn new(sketch: &str, config_digest: String, seed: u64) -> Self {
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_secs());
+    let dirty = is_workspace_dirty();
+    Self {
+      sketch: sketch.to_string(),
+      git_commit: generate_git_hash(),
+      dirty,
+      diff_digest: dirty.then(generate_diff_hash),
+      config_digest,
+      seed,
+      crate_versions: BTreeMap::new(),
+      timestamp,
+    }
+  }
+
+  pub fn with_crate_version(mut self, name: &str, version: &str) -> Self {
+    self
+      .crate_versions
+      .insert(name.to_string(), version.to_string());
+    self
+  }
+
+  // The commit, marked when the workspace had uncommitted changes.
+  pub fn git_revision(&self) -> String {
+    match self.dirty {
+      true => format!("{}{}", self.git_commit, DIRTY_SUFFIX),
+      false => self.git_commit.clone(),
+    }
+  }
+
+  // The parts naming the render, from the code to the seed.
+  fn hashes(&self) -> [String; 3] {
+    [
+      self.git_revision(),
+      self.config_digest.clone(),
+      generate_seed_hash(self.seed),
+    ]
   }
 
   pub fn generate_filename(&self) -> String {
-    self.hashes.join("_")
+    self.hashes().join("_")
   }
 
   pub fn generate_title(&self) -> String {
-    self.hashes.join(" - ")
+    self.hashes().join(" - ")
+  }
+
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string_pretty(self)?)
+  }
+
+  pub fn to_toml(&self) -> Result<String> {
+    // Going through a value puts the crate versions table after the plain fields.
+    Ok(toml::to_string(&toml::Value::try_from(self)?)?)
+  }
+
+  // Write the manifest of the render in `directory`, as `signature.<format>`.
+  pub fn save_manifest(&self, directory: &Path, format: ManifestFormat) -> Result<()> {
+    let manifest = match format {
+      ManifestFormat::Json => self.to_json()?,
+      ManifestFormat::Toml => self.to_toml()?,
+    };
+    std::fs::write(manifest_path(directory, format), manifest)?;
+    Ok(())
+  }
+
+  // Read a manifest written by `save_manifest`, in the format given by its extension.
+  pub fn load_manifest(path: &Path) -> Result<Self> {
+    let manifest = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+      Some("toml") => Ok(toml::from_str(&manifest)?),
+      _ => Ok(serde_json::from_str(&manifest)?),
+    }
+  }
+
+  // Read the signature `reference` stands for: the path of a manifest, of a directory holding one,
+  // or a signature as found in the names and titles of the renders.
+  pub fn resolve(reference: &str) -> Result<Self> {
+    let path = Path::new(reference);
+    if path.is_file() {
+      return Self::load_manifest(path);
+    }
+    if path.is_dir() {
+      return match [ManifestFormat::Json, ManifestFormat::Toml]
+        .iter()
+        .map(|format| manifest_path(path, *format))
+        .find(|manifest| manifest.is_file())
+      {
+        Some(manifest) => Self::load_manifest(&manifest),
+        None => Err(Error::Parse(format!(
+          "no signature manifest found in {}",
+          path.display()
+        ))),
+      };
+    }
+    reference.parse().map_err(Error::Parse)
+  }
+}
+
+// Parse a signature from a file name or a title, the fields it leaves out are left empty.
+impl FromStr for Signature {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    let invalid = || {
+      format!(
+        "invalid signature '{}', expected <commit>_<config digest>_<seed> or a manifest",
+        s
+      )
+    };
+    let s = s.trim();
+    let parts: Vec<&str> = match s.contains(" - ") {
+      true => s.split(" - ").collect(),
+      false => s.split('_').collect(),
+    };
+    match parts.as_slice() {
+      [git_commit, config_digest, seed] => Ok(Self {
+        git_commit: git_commit.trim_end_matches(DIRTY_SUFFIX).to_string(),
+        dirty: git_commit.ends_with(DIRTY_SUFFIX),
+        config_digest: config_digest.to_string(),
+        seed: seed.parse().map_err(|_| invalid())?,
+        ..Self::default()
+      }),
+      _ => Err(invalid()),
+    }
+  }
+}
+
+fn manifest_path(directory: &Path, format: ManifestFormat) -> PathBuf {
+  directory
+    .join("signature")
+    .with_extension(format.extension())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ManifestFormat {
+  Json,
+  Toml,
+}
+
+impl ManifestFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ManifestFormat::Json => "json",
+      ManifestFormat::Toml => "toml",
+    }
+  }
+}
+
+impl FromStr for ManifestFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s.trim() {
+      "json" => Ok(ManifestFormat::Json),
+      "toml" => Ok(ManifestFormat::Toml),
+      _ => Err(format!(
+        "invalid manifest format '{}', expected json or toml",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for ManifestFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.extension())
   }
 }
 
@@ -24,12 +222,129 @@ pub fn generate_git_hash() -> String {
     .unwrap_or("".to_string())
 }
 
+// Whether tracked files of the workspace outside of the configs have uncommitted changes, false if
+// git cannot tell.
+pub fn is_workspace_dirty() -> bool {
+  git(&[
+    "status",
+    "--porcelain",
+    "--untracked-files=no",
+    "--",
+    IGNORED_CHANGES,
+  ])
+  .is_some_and(|status| !status.trim().is_empty())
+}
+
+// SHA-256 of the uncommitted changes of the workspace outside of the configs, empty if git cannot
+// tell.
+pub fn generate_diff_hash() -> String {
+  git(&["diff", "HEAD", "--", IGNORED_CHANGES])
+    .map(|diff| sha256::digest_bytes(diff.as_bytes()))
+    .unwrap_or("".to_string())
+}
+
+// The output of a git command run in the current directory, if it succeeded.
+fn git(args: &[&str]) -> Option<String> {
+  let output = Command::new("git").args(args).output().ok()?;
+  output
+    .status
+    .success()
+    .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn generate_hash(bytes: &[u8]) -> String {
+  sha256::digest_bytes(bytes)
+}
+
 pub fn generate_file_hash(path: &str) -> String {
   std::fs::read(path)
-    .map(|bytes| sha256::digest_bytes(&bytes))
+    .map(|bytes| generate_hash(&bytes))
     .unwrap_or("".to_string())
 }
 
 pub fn generate_seed_hash(seed: u64) -> String {
   seed.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn signature(dirty: bool) -> Signature {
+    Signature {
+      sketch: "spinners".to_string(),
+      git_commit: "abc1234".to_string(),
+      dirty,
+      diff_digest: dirty.then(|| "f00d".to_string()),
+      config_digest: "0123456789abcdef".to_string(),
+      seed: 42,
+      crate_versions: BTreeMap::from([("display".to_string(), "0.1.0".to_string())]),
+      timestamp: 1650000000,
+    }
+  }
+
+  // A directory of its own for the test, emptied beforehand.
+  fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("signature-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+  }
+
+  #[test]
+  fn parses_titles_and_filenames() {
+    let signature = signature(false);
+    for reference in [signature.generate_title(), signature.generate_filename()] {
+      let parsed: Signature = reference.parse().unwrap();
+      assert_eq!(parsed.git_commit, "abc1234");
+      assert!(!parsed.dirty);
+      assert_eq!(parsed.config_digest, "0123456789abcdef");
+      assert_eq!(parsed.seed, 42);
+      assert_eq!(parsed.generate_title(), signature.generate_title());
+    }
+  }
+
+  #[test]
+  fn parses_the_dirty_suffix() {
+    let signature = signature(true);
+    assert_eq!(signature.git_revision(), "abc1234-dirty");
+    for reference in [signature.generate_title(), signature.generate_filename()] {
+      let parsed: Signature = reference.parse().unwrap();
+      assert_eq!(parsed.git_commit, "abc1234");
+      assert!(parsed.dirty);
+    }
+  }
+
+  #[test]
+  fn rejects_malformed_signatures() {
+    for reference in ["", "abc1234_0123", "abc1234_0123_seed", "a - b - c - d"] {
+      assert!(reference.parse::<Signature>().is_err(), "{}", reference);
+    }
+  }
+
+  #[test]
+  fn resolves_manifests() {
+    let signature = signature(true);
+    for format in [ManifestFormat::Json, ManifestFormat::Toml] {
+      let directory = test_directory(format.extension());
+      signature.save_manifest(&directory, format).unwrap();
+      let manifest = manifest_path(&directory, format);
+      assert_eq!(
+        Signature::resolve(manifest.to_str().unwrap()).unwrap(),
+        signature
+      );
+      assert_eq!(
+        Signature::resolve(directory.to_str().unwrap()).unwrap(),
+        signature
+      );
+      std::fs::remove_dir_all(&directory).unwrap();
+    }
+  }
+
+  #[test]
+  fn fails_to_resolve_a_directory_without_manifest() {
+    let directory = test_directory("empty");
+    assert!(Signature::resolve(directory.to_str().unwrap()).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+  }
+}
@@ -1,4 +1,6 @@
 use clap::Parser;
+use display::{OutputArgs, Preview};
+use signature::ManifestFormat;
 
 pub fn parse_cli_args() -> CliArgs {
   CliArgs::parse()
@@ -12,4 +14,36 @@ pub struct CliArgs {
   pub config_file: Option<String>,
   #[clap(short, long)]
   pub name: Option<String>,
+  /// Render on the CPU without opening a preview window
+  #[clap(long)]
+  pub headless: bool,
+  /// Preview window size: <max side> in pixels, scale:<factor> of the texture, or none to render
+  /// on the GPU without a window
+  #[clap(long)]
+  pub preview: Option<Preview>,
+  /// Render again from a signature, a manifest or a directory holding one, with the config and the
+  /// seed it was made from
+  #[clap(long, conflicts_with = "seed")]
+  pub reproduce: Option<String>,
+  /// Leave the signature out of the final frame
+  #[clap(long)]
+  pub unsigned: bool,
+  /// Refuse to render from a workspace with uncommitted changes
+  #[clap(long)]
+  pub require_clean: bool,
+  /// Directory where every config rendered is kept under its digest (defaults to configs/archive)
+  #[clap(long)]
+  pub config_archive: Option<std::path::PathBuf>,
+  /// Render this many seeds, from the given one on, into a proof pdf with a page per seed
+  /// captioned with its signature
+  #[clap(long)]
+  pub proof: Option<u32>,
+  /// Draw a new seed when the config file changes, instead of keeping the current one
+  #[clap(long)]
+  pub reseed_on_reload: bool,
+  /// Format of the signature manifest written beside the frames, json or toml
+  #[clap(long, default_value = "json")]
+  pub manifest: ManifestFormat,
+  #[clap(flatten)]
+  pub output: OutputArgs,
 }
@@ -1,31 +1,41 @@
+use display::{Canvas, Effect, LayerOptions, Preview, SavePolicy};
 use nannou::prelude::{Point2, Srgb};
-use serde::Deserialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 pub fn load_config(path: String) -> Config {
   match Config::new(path) {
     Ok(config) => config,
     Err(err) => {
-      println!("Encountered error: {}.\nProcess exited with code 1", err);
+      eprintln!("Encountered error: {}.\nProcess exited with code 1", err);
       std::process::exit(1);
     }
   }
 }
 
-#[derive(Debug, Deserialize)]
+// Printing DPI assumed for canvases given in pixels.
+const DEFAULT_DPI: f32 = 300.;
+// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WindowConfig {
   pub background_color: Srgb<u8>,
-  pub height: u32,
-  pub width: u32,
+  // Size of the texture in pixels, ignored when the size is given by a canvas.
+  pub height: Option<u32>,
+  pub width: Option<u32>,
+  pub preview: Option<Preview>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct SpinnerDrawingConfig {
   pub color: Srgb<u8>,
   pub point_weight: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct SpinnerConfig {
   pub center: Point2,
   pub density: Option<f32>,
@@ -37,7 +47,7 @@ pub struct SpinnerConfig {
   pub theta_max: Option<f32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct SpinnerDefaultConfig {
   pub density: f32,
   pub density_factor: f32,
@@ -48,19 +58,191 @@ pub struct SpinnerDefaultConfig {
   pub theta_max: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Config {
+  pub author: Option<String>,
+  pub license: Option<String>,
   pub window: WindowConfig,
+  pub canvas: Option<Canvas>,
   pub signature_color: Srgb<u8>,
+  pub save_policy: Option<SavePolicy>,
+  #[serde(default)]
+  pub post_processing: Vec<Effect>,
+  // Options of the layers, by name.
+  #[serde(default)]
+  pub layers: BTreeMap<String, LayerOptions>,
   pub spinners: Vec<SpinnerConfig>,
   pub spinner_default_config: SpinnerDefaultConfig,
 }
 
 impl Config {
   pub fn new(path: String) -> Result<Self, config::ConfigError> {
-    config::Config::builder()
-      .add_source(config::File::from(Path::new(&path)))
+    Self::from_source(config::File::from(Path::new(&path)))
+  }
+
+  fn from_source<S: config::Source + Send + Sync + 'static>(
+    source: S,
+  ) -> Result<Self, config::ConfigError> {
+    let config: Self = config::Config::builder()
+      .add_source(source)
       .build()?
-      .try_deserialize()
+      .try_deserialize()?;
+    if config.canvas.is_none() && (config.window.width.is_none() || config.window.height.is_none())
+    {
+      let err = "either a canvas or the window width and height are required";
+      return Err(config::ConfigError::Message(err.to_string()));
+    }
+    Ok(config)
+  }
+
+  // The print the texture is derived from.
+  pub fn canvas(&self) -> Canvas {
+    match (self.canvas, self.window.width, self.window.height) {
+      (Some(canvas), _, _) => canvas,
+      (None, Some(width), Some(height)) => Canvas::from_pixels([width, height], DEFAULT_DPI),
+      _ => unreachable!("the canvas size is checked when loading the config"),
+    }
+  }
+
+  // The config as it is rendered: the canvas in place of the window size, and every spinner with
+  // the defaults it leaves out.
+  pub fn resolved(mut self) -> Self {
+    self.canvas = Some(self.canvas());
+    self.window.width = None;
+    self.window.height = None;
+    let defaults = &self.spinner_default_config;
+    for spinner in &mut self.spinners {
+      spinner.density.get_or_insert(defaults.density);
+      spinner
+        .density_factor
+        .get_or_insert(defaults.density_factor);
+      spinner
+        .drawing
+        .get_or_insert_with(|| defaults.drawing.clone());
+      spinner
+        .initial_points
+        .get_or_insert(defaults.initial_points);
+      spinner.radius.get_or_insert(defaults.radius);
+      spinner
+        .theta_increment
+        .get_or_insert(defaults.theta_increment);
+      spinner.theta_max.get_or_insert(defaults.theta_max);
+    }
+    self
+  }
+
+  // Serialize the configuration as it was resolved from the file.
+  pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+    toml::to_string(&toml::Value::try_from(self)?)
+  }
+
+  // The parameters of the artwork, leaving out how the render is previewed, saved and credited.
+  pub fn artwork(&self) -> ArtworkConfig {
+    let config = self.clone().resolved();
+    ArtworkConfig {
+      canvas: config.canvas(),
+      background_color: config.window.background_color,
+      signature_color: config.signature_color,
+      post_processing: config.post_processing,
+      layers: config.layers,
+      spinners: config.spinners,
+    }
+  }
+
+  // SHA-256 of the serialized parameters of the artwork, the same for the configs rendering the same
+  // artwork whatever the formatting and comments of their files.
+  pub fn digest(&self) -> String {
+    toml::Value::try_from(self.artwork())
+      .and_then(|artwork| toml::to_string(&artwork))
+      .map(|toml| signature::generate_hash(toml.as_bytes()))
+      .unwrap_or("".to_string())
+  }
+}
+
+// What the artwork rendered from a config depends on, every spinner having the defaults it leaves
+// out filled in.
+#[derive(Serialize)]
+pub struct ArtworkConfig {
+  pub canvas: Canvas,
+  pub background_color: Srgb<u8>,
+  pub signature_color: Srgb<u8>,
+  pub post_processing: Vec<Effect>,
+  pub layers: BTreeMap<String, LayerOptions>,
+  pub spinners: Vec<SpinnerConfig>,
+}
+
+// Tells when the config file was modified, checking it at most every `WATCH_INTERVAL`.
+pub struct ConfigWatcher {
+  path: PathBuf,
+  modified: Option<SystemTime>,
+  checked: Instant,
+}
+
+impl ConfigWatcher {
+  pub fn new(path: &str) -> Self {
+    let path = PathBuf::from(path);
+    Self {
+      modified: modified(&path),
+      path,
+      checked: Instant::now(),
+    }
+  }
+
+  pub fn changed(&mut self) -> bool {
+    if self.checked.elapsed() < WATCH_INTERVAL {
+      return false;
+    }
+    self.checked = Instant::now();
+    let modified = modified(&self.path);
+    if modified == self.modified {
+      return false;
+    }
+    self.modified = modified;
+    true
+  }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  std::fs::metadata(path)
+    .and_then(|metadata| metadata.modified())
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DEFAULT_CONFIG: &str = include_str!("../../configs/spinners/default.toml");
+
+  fn parse(toml: &str) -> Config {
+    let source = config::File::from_str(toml, config::FileFormat::Toml);
+    Config::from_source(source).unwrap()
+  }
+
+  #[test]
+  fn digest_ignores_comments_and_whitespace() {
+    let edited = format!(
+      "# A comment\n\n{}\n\n# Another one\n",
+      DEFAULT_CONFIG.replace(" = ", "   =   ")
+    );
+    assert_eq!(parse(DEFAULT_CONFIG).digest(), parse(&edited).digest());
+  }
+
+  #[test]
+  fn digest_ignores_preview_and_saving() {
+    let config = parse(DEFAULT_CONFIG);
+    let mut previewed = config.clone();
+    previewed.window.preview = Some(Preview::Scale(0.5));
+    previewed.save_policy = Some(SavePolicy::FinalFrame);
+    previewed.author = Some("Someone else".to_string());
+    assert_eq!(config.digest(), previewed.digest());
+  }
+
+  #[test]
+  fn digest_follows_the_artwork() {
+    let config = parse(DEFAULT_CONFIG);
+    let mut changed = config.clone();
+    changed.spinner_default_config.radius += 1.;
+    assert_ne!(config.digest(), changed.digest());
   }
 }
@@ -6,20 +6,63 @@ mod spinner;
 use crate::cli::parse_cli_args;
 use crate::config::{load_config, Config};
 use crate::spinner::SpinnerDrawOptions;
-use display::DisplayDriver;
+use display::metadata::{self, Metadata};
+use display::run::{check_golden, fail, finish};
+use display::{
+  Canvas, Command, DisplayDriver, DrawList, EncoderOptions, FailurePolicy, GoldenOptions,
+  OutputFormat, PdfDocument, PostPipeline, Preview, SavePolicy, TilingOptions, WriterOptions,
+  DEFAULT_LAYER,
+};
 use lazy_static::lazy_static;
 use model::Model;
 use nannou::prelude::*;
+use signature::{ConfigArchive, ManifestFormat, Signature};
+use std::path::{Path, PathBuf};
 
 const CONFIG_DEFAULT_PATH: &str = "configs/spinners/default.toml";
+const CONFIG_ARCHIVE_PATH: &str = "configs/archive";
 const NADOU: &str = "Nadou";
+// Largest side of the preview window by default, in pixels.
+const PREVIEW_SIZE: u32 = 800;
+// The background is drawn on the default layer, below the spinners and the signature.
+const ARTWORK_LAYER: &str = "artwork";
+const SIGNATURE_LAYER: &str = "signature";
+const LAYERS: [&str; 3] = [DEFAULT_LAYER, ARTWORK_LAYER, SIGNATURE_LAYER];
 
 lazy_static! {
-  static ref CONFIG_PATH: String = parse_cli_args()
-    .config_file
-    .unwrap_or(CONFIG_DEFAULT_PATH.to_string());
-  static ref CONFIG: Config = load_config(CONFIG_PATH.to_string());
+  // Path of the config and seed of the render to make again.
+  static ref REPRODUCTION: Option<(String, u64)> =
+    parse_cli_args().reproduce.as_deref().map(reproduce);
+  static ref CONFIG_PATH: String = match &*REPRODUCTION {
+    Some((config_path, _)) => config_path.clone(),
+    None => parse_cli_args()
+      .config_file
+      .unwrap_or(CONFIG_DEFAULT_PATH.to_string()),
+  };
   static ref NAME: String = parse_cli_args().name.unwrap_or("".to_string());
+  static ref HEADLESS: bool = parse_cli_args().headless;
+  static ref FORMATS: Vec<OutputFormat> = parse_cli_args().output.formats;
+  // Whether the spinners are drawn as lines a pen plotter can follow rather than as points.
+  static ref PLOTTING: bool = FORMATS.iter().any(OutputFormat::is_plot);
+  static ref FAILURE_POLICY: FailurePolicy = parse_cli_args().output.on_failure;
+  static ref WRITERS: WriterOptions = parse_cli_args().output.writer_options();
+  static ref SEED: Option<u64> =
+    REPRODUCTION.as_ref().map(|(_, seed)| *seed).or(parse_cli_args().output.seed);
+  static ref PROOF: Option<u32> = parse_cli_args().proof;
+  static ref RESEED_ON_RELOAD: bool = parse_cli_args().reseed_on_reload;
+  static ref MANIFEST: ManifestFormat = parse_cli_args().manifest;
+  static ref ARCHIVE: ConfigArchive = ConfigArchive::new(
+    parse_cli_args().config_archive.unwrap_or_else(|| {
+      display::project_path()
+        .expect("Could not locate project_path")
+        .join(CONFIG_ARCHIVE_PATH)
+    })
+  );
+  static ref GOLDEN: Option<PathBuf> = parse_cli_args().output.golden;
+  static ref GOLDEN_OPTIONS: GoldenOptions = parse_cli_args().output.golden_options();
+  static ref TILING: Option<TilingOptions> = parse_cli_args().output.tiling_options();
+  static ref ENCODER: Option<EncoderOptions> =
+    parse_cli_args().output.encoder_options();
   // static ref N_ITERATIONS: usize = CONFIG
   //   .spinners
   //   .iter()
@@ -38,123 +81,432 @@ lazy_static! {
 }
 
 fn main() {
-  nannou::app(model)
-    .update(update)
-    .loop_mode(LoopMode::NTimes {
-      number_of_updates: *N_ITERATIONS,
-    })
-    .exit(exit)
-    .run();
+  if parse_cli_args().require_clean && signature::is_workspace_dirty() {
+    fail("the workspace has uncommitted changes, commit them or leave out --require-clean");
+  }
+  let config = load_config(CONFIG_PATH.to_string());
+  if let Some(count) = *PROOF {
+    return proof(config, count);
+  }
+  if *HEADLESS || preview(&config) == Preview::Hidden {
+    return headless(config);
+  }
+  nannou::app(model).update(update).exit(exit).run();
 }
 
 fn model(app: &App) -> Model {
-  let texture_size = [CONFIG.window.width, CONFIG.window.height];
-  let [win_w, win_h] = [texture_size[0] / 4, texture_size[1] / 4];
+  let config = load_config(CONFIG_PATH.to_string());
+  let texture_size = config.canvas().texture_size();
+  let [win_w, win_h] = preview(&config)
+    .window_size(texture_size)
+    .expect("the preview window is hidden");
   let w_id = app
     .new_window()
     .size(win_w, win_h)
     .view(view)
+    .key_pressed(key_pressed)
     .build()
     .unwrap();
   let window = app.window(w_id).unwrap();
 
-  let model = Model::new(
-    &CONFIG,
-    &CONFIG_PATH,
-    DisplayDriver::new(&window, texture_size),
-  );
-  // Make sure the directory where we will save images to exists.
-  std::fs::create_dir_all(&capture_directory(app, &model)).unwrap();
+  eprintln!("Press H in the preview window for the keyboard controls");
+  let display_driver = DisplayDriver::new(&window, texture_size);
+  init_model(display_driver, config, *SEED).unwrap_or_else(|err| fail(err))
+}
 
-  model
+fn headless(config: Config) {
+  let display_driver = windowless_driver(&config.canvas());
+  let mut model = init_model(display_driver, config, *SEED).unwrap_or_else(|err| fail(err));
+  for elapsed_frames in 0..*N_ITERATIONS as u64 {
+    step(&mut model, elapsed_frames);
+  }
+  if let Some(golden) = &*GOLDEN {
+    check_golden(&mut model.display_driver, golden, &GOLDEN_OPTIONS);
+  }
+  finish(&mut model.display_driver);
+}
+
+// Render `count` seeds without preview, from the given one on, into a proof document with a page per
+// seed captioned with its signature.
+fn proof(config: Config, count: u32) {
+  let first_seed = SEED.unwrap_or_else(rand::random);
+  let mut document = PdfDocument::new();
+  for seed in first_seed..first_seed + count as u64 {
+    let display_driver = windowless_driver(&config.canvas());
+    let mut model =
+      init_model(display_driver, config.clone(), Some(seed)).unwrap_or_else(|err| fail(err));
+    for elapsed_frames in 0..*N_ITERATIONS as u64 {
+      step(&mut model, elapsed_frames);
+    }
+    let title = model.signature().generate_title();
+    match model.display_driver.pdf_page(Some(&title)) {
+      Ok(page) => document.push(page),
+      Err(err) => fail(err),
+    }
+    finish(&mut model.display_driver);
+  }
+  let path = display::project_path()
+    .expect("Could not locate project_path")
+    .join("frames")
+    .join("spinners")
+    .join(NAME.as_str())
+    .join(format!("proof-{}-{}.pdf", first_seed, document.len()));
+  eprintln!("Saving {}", path.display());
+  if let Err(err) = document.save(&path) {
+    fail(err);
+  }
+}
+
+fn windowless_driver(canvas: &Canvas) -> DisplayDriver {
+  let texture_size = canvas.texture_size();
+  match *HEADLESS {
+    true => DisplayDriver::headless(texture_size),
+    false => DisplayDriver::offscreen(texture_size),
+  }
+}
+
+// Build the model and record what it renders, the errors being left to the caller so that a reload
+// keeps the session going.
+fn init_model(
+  display_driver: DisplayDriver,
+  config: Config,
+  seed: Option<u64>,
+) -> Result<Model, Box<dyn std::error::Error>> {
+  let config = config.resolved();
+  let canvas = config.canvas();
+  let mut display_driver = display_driver
+    .with_formats(&FORMATS)
+    .with_save_policy(save_policy(&config))
+    .with_failure_policy(*FAILURE_POLICY)
+    .with_writers(*WRITERS)
+    .with_post_processing(PostPipeline::new(&config.post_processing)?)
+    .with_plotter(parse_cli_args().output.plotter_options(canvas));
+  // Vector pages keep every point drawn, only when they are needed.
+  if FORMATS.contains(&OutputFormat::Pdf) || PROOF.is_some() {
+    display_driver = display_driver.with_pdf(parse_cli_args().output.pdf_options(canvas));
+  }
+  for name in LAYERS {
+    let options = config.layers.get(name).copied().unwrap_or_default();
+    display_driver = display_driver.with_layer(name, options);
+  }
+  if let Some(tiling) = *TILING {
+    display_driver = display_driver.with_tiling(tiling);
+  }
+  if let Some(progress) = parse_cli_args().output.progress(Some(*N_ITERATIONS as u64)) {
+    display_driver = display_driver.with_progress(progress);
+  }
+  let mut model = Model::new(config, &CONFIG_PATH, seed, display_driver);
+  model.signed = !parse_cli_args().unsigned;
+  prepare_output(&model)?;
+  ARCHIVE.store(&model.config.digest(), model.config.to_toml()?.as_bytes())?;
+  if let Some(options) = ENCODER.clone() {
+    let path = output_directory(&model).join("animation");
+    model.display_driver.set_encoder(path, options);
+  }
+  let provenance = provenance(&model);
+  model.display_driver.set_metadata(provenance);
+
+  Ok(model)
+}
+
+// Digest of the artwork of the config file at `path`, if it can be loaded.
+fn config_digest(path: &Path) -> Option<String> {
+  let config = Config::new(path.to_str()?.to_string()).ok()?;
+  Some(config.digest())
+}
+
+fn preview(config: &Config) -> Preview {
+  parse_cli_args()
+    .preview
+    .or(config.window.preview)
+    .unwrap_or(Preview::Fit(PREVIEW_SIZE))
+}
+
+fn save_policy(config: &Config) -> SavePolicy {
+  parse_cli_args()
+    .output
+    .save_policy
+    .or_else(|| config.save_policy.clone())
+    .unwrap_or_default()
+}
+
+// What is needed to trace a saved image back to the render that produced it.
+fn provenance(model: &Model) -> Metadata {
+  let mut provenance = Metadata::new();
+  let author = model.config.author.clone().unwrap_or(NADOU.to_string());
+  provenance.insert(metadata::AUTHOR.to_string(), author);
+  if let Some(license) = &model.config.license {
+    provenance.insert(metadata::LICENSE.to_string(), license.clone());
+  }
+  provenance.insert(
+    metadata::SIGNATURE.to_string(),
+    model.signature().generate_title(),
+  );
+  let signature = model.signature();
+  provenance.insert(metadata::GIT_COMMIT.to_string(), signature.git_revision());
+  provenance.insert(
+    metadata::CONFIG_DIGEST.to_string(),
+    signature.config_digest.clone(),
+  );
+  provenance.insert(metadata::SEED.to_string(), signature.seed.to_string());
+  if let Ok(config) = model.config.to_toml() {
+    provenance.insert(metadata::CONFIG.to_string(), config);
+  }
+  provenance
 }
 
 fn update(app: &App, model: &mut Model, _update: Update) {
-  let background_color: Srgb<f32> = CONFIG.window.background_color.into_format();
-  // Reset the `draw` state.
-  let draw = model.display_driver.draw();
-  draw.reset();
-  if app.elapsed_frames() == 0 {
-    draw.background().color(background_color);
+  if model.watcher.changed() {
+    reload(app, model);
+  }
+  if model.iteration < *N_ITERATIONS && model.controls.should_step() {
+    step(model, model.iteration as u64);
+  }
+  let mut overlay = model.controls.overlay();
+  overlay.extend(model.reload_error.clone());
+  model.display_driver.set_overlay(overlay);
+  // The signature is shown over the preview until it is drawn on the final frame.
+  let preview_draw = model.display_driver.preview_draw();
+  preview_draw.reset();
+  if model.signed && model.iteration < *N_ITERATIONS {
+    draw_signature(preview_draw, model);
+  }
+}
+
+// Rebuild the model from the config file after it changed, and render again from the first frame
+// with the same seed unless asked otherwise. The current model is kept when the config cannot be
+// loaded.
+fn reload(app: &App, model: &mut Model) {
+  let reloaded = Config::new(CONFIG_PATH.to_string())
+    .map_err(|err| err.to_string())
+    .and_then(|config| {
+      let texture_size = config.canvas().texture_size();
+      let display_driver = DisplayDriver::new(&app.main_window(), texture_size);
+      let seed = (!*RESEED_ON_RELOAD).then(|| model.seed());
+      init_model(display_driver, config, seed).map_err(|err| err.to_string())
+    });
+  match reloaded {
+    Ok(mut reloaded) => {
+      std::mem::swap(&mut reloaded.controls, &mut model.controls);
+      reloaded.signed = model.signed;
+      let mut previous = std::mem::replace(model, reloaded);
+      finish(&mut previous.display_driver);
+      eprintln!("Reloaded {} with seed {}", *CONFIG_PATH, model.seed());
+    }
+    Err(err) => {
+      eprintln!("Could not reload {}: {}", *CONFIG_PATH, err);
+      model.reload_error = Some(format!("Could not reload the config: {}", err));
+    }
+  }
+}
+
+fn step(model: &mut Model, elapsed_frames: u64) {
+  let background_color: Srgb<f32> = model.config.window.background_color.into_format();
+  // Reset the `draw` state of every layer.
+  model.display_driver.reset();
+  if elapsed_frames == 0 {
+    model.display_driver.draw().background(background_color);
   }
 
   for spinner in model.spinners.iter_mut() {
     let points = spinner.compute_points(model.iteration);
     let options = &spinner.draw_options;
-    for point in &points {
-      draw_point(&draw, point, options);
+    // Splat the points straight into the density buffer when the artwork accumulates them.
+    match model.display_driver.density(ARTWORK_LAYER) {
+      Some(density) => density.splat_points(&points, options.color),
+      None => {
+        let artwork = layer(&model.display_driver, ARTWORK_LAYER);
+        for point in &points {
+          draw_point(artwork, point, options);
+        }
+      }
+    }
+    // Plotters draw the points as a line rather than as dots.
+    if *PLOTTING {
+      layer(&model.display_driver, ARTWORK_LAYER).plot_path(&points);
     }
   }
 
-  if model.iteration == *N_ITERATIONS - 1 {
-    draw_signature(draw, &model);
+  if model.signed && model.iteration == *N_ITERATIONS - 1 {
+    draw_signature(layer(&model.display_driver, SIGNATURE_LAYER), model);
   }
 
   model.iteration += 1;
   // Render our drawing to the texture.
-  let window = app.main_window();
-  model
-    .display_driver
-    .save(&window, capture_directory(app, model))
+  let path = capture_directory(model, elapsed_frames);
+  if let Err(err) = model.display_driver.update(elapsed_frames, path) {
+    fail(err);
+  }
+}
+
+fn key_pressed(_app: &App, model: &mut Model, key: Key) {
+  match model.controls.key_pressed(key) {
+    Some(Command::Snapshot) => snapshot(model),
+    Some(Command::Restart) => restart(model),
+    Some(Command::ToggleSignature) => toggle_signature(model),
+    _ => {}
+  }
+}
+
+// Save the frame currently shown, whether the sketch is running or not.
+fn snapshot(model: &mut Model) {
+  if model.iteration == 0 {
+    return;
+  }
+  let path = capture_directory(model, model.iteration as u64 - 1);
+  if let Err(err) = model.display_driver.save(path) {
+    fail(err);
+  }
+}
+
+// Show or hide the signature, until it is drawn on the final frame.
+fn toggle_signature(model: &mut Model) {
+  if model.iteration < *N_ITERATIONS {
+    model.signed = !model.signed;
+  } else {
+    eprintln!(
+      "The final frame was rendered {}, press R to render it again",
+      if model.signed { "signed" } else { "unsigned" }
+    );
+  }
+}
+
+// Start the render over with a new seed.
+fn restart(model: &mut Model) {
+  if let Err(err) = model.display_driver.restart() {
+    fail(err);
+  }
+  model.reseed(None);
+  if let Err(err) = prepare_output(model) {
+    fail(err);
+  }
+  let provenance = provenance(model);
+  model.display_driver.set_metadata(provenance);
+  eprintln!("Restarted with seed {}", model.seed());
 }
 
 fn view(_app: &App, model: &Model, frame: Frame) {
   model.display_driver.render(frame);
 }
 
-fn exit(app: &App, model: Model) {
-  let window = app.main_window();
-  model.display_driver.wait(&window);
+fn exit(_app: &App, mut model: Model) {
+  finish(&mut model.display_driver);
 }
 
-fn draw_point(draw: &Draw, point: &Point2, options: &SpinnerDrawOptions) {
-  draw
-    .ellipse()
-    .xy(*point)
-    .w_h(options.point_weight, options.point_weight)
-    .color(options.color);
+fn layer<'a>(display_driver: &'a DisplayDriver, name: &str) -> &'a DrawList {
+  display_driver
+    .layer(name)
+    .expect("the layer was added in init_model")
 }
 
-fn draw_signature(draw: &Draw, model: &Model) {
-  // signing the frame
-  let w = CONFIG.window.width as f32;
-  let h = CONFIG.window.height as f32 * 0.05;
-  let x = 0.;
-  let y = -(CONFIG.window.height as f32) / 2. + h / 2.;
+fn draw_point(draw: &DrawList, point: &Point2, options: &SpinnerDrawOptions) {
+  draw.ellipse(*point, Vec2::splat(options.point_weight), options.color);
+}
 
-  let signature_rect = Rect::from_x_y_w_h(x, y, w, h);
+fn draw_signature(draw: &DrawList, model: &Model) {
+  // signing the frame, within the margins of the print
+  let safe_area = model.config.canvas().safe_area();
+  let signature_rect = Rect::from_w_h(safe_area.w(), safe_area.h() * 0.05)
+    .align_middle_x_of(safe_area)
+    .align_bottom_of(safe_area);
   let nadou_rect = Rect::from_w_h(signature_rect.w() * 0.1, signature_rect.h() * 0.5)
     .align_right_of(signature_rect)
     .align_bottom_of(signature_rect);
   let hash_rect =
     Rect::from_w_h(signature_rect.w() * 0.5, signature_rect.h() * 0.5).above(signature_rect);
 
-  draw
-    .text(model.signature().generate_title().as_ref())
-    .font_size(hash_rect.h() as u32 / 4)
-    .x(hash_rect.x())
-    .y(hash_rect.bottom())
-    .wh(hash_rect.wh())
-    .color(CONFIG.signature_color);
+  draw.text(
+    model.signature().generate_title().as_ref(),
+    hash_rect.shift_y(-hash_rect.h() / 2.),
+    hash_rect.h() as u32 / 4,
+    model.config.signature_color,
+  );
 
-  draw
-    .text(NADOU)
-    .font_size(nadou_rect.h() as u32 / 3)
-    .x(nadou_rect.x())
-    .y(nadou_rect.top())
-    .wh(nadou_rect.wh())
-    .color(CONFIG.signature_color);
+  draw.text(
+    NADOU,
+    nadou_rect.shift_y(nadou_rect.h() / 2.),
+    nadou_rect.h() as u32 / 3,
+    model.config.signature_color,
+  );
 }
 
-// The directory where we'll save the frames.
-fn capture_directory(app: &nannou::app::App, model: &Model) -> std::path::PathBuf {
-  let elapsed_frames = app.main_window().elapsed_frames();
-  app
-    .project_path()
+// Find what a render was made from, checking it can be made again from the current code: the
+// config whose digest it holds is looked for in the archive, then next to the config file.
+fn reproduce(reference: &str) -> (String, u64) {
+  let signature = Signature::resolve(reference).unwrap_or_else(|err| fail(err));
+  let git_commit = signature::generate_git_hash();
+  if signature.git_commit.is_empty() || signature.git_commit != git_commit {
+    fail(format!(
+      "the render was made at commit '{}' but the workspace is at '{}', check it out first",
+      signature.git_commit, git_commit
+    ));
+  }
+  match (signature.dirty, signature::is_workspace_dirty()) {
+    (true, false) => {
+      fail("the render was made with uncommitted changes the workspace does not have")
+    }
+    (false, true) => fail("the workspace has uncommitted changes, stash them first"),
+    (true, true) => match &signature.diff_digest {
+      Some(digest) if *digest != signature::generate_diff_hash() => {
+        fail("the uncommitted changes of the workspace differ from the ones of the render")
+      }
+      Some(_) => {}
+      None => eprintln!("The uncommitted changes the render was made with cannot be checked"),
+    },
+    (false, false) => {}
+  }
+  let config_file = parse_cli_args()
+    .config_file
+    .unwrap_or(CONFIG_DEFAULT_PATH.to_string());
+  let digest = Some(signature.config_digest.clone());
+  let config_path = match config_digest(Path::new(&config_file)) == digest {
+    true => Some(PathBuf::from(config_file)),
+    false => ARCHIVE.lookup(&signature.config_digest).or_else(|| {
+      let directory = Path::new(&config_file).parent()?;
+      std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| path.is_file() && config_digest(path) == digest)
+    }),
+  };
+  match config_path.and_then(|path| path.to_str().map(str::to_string)) {
+    Some(config_path) => {
+      eprintln!(
+        "Reproducing {} from {}",
+        signature.generate_title(),
+        config_path
+      );
+      (config_path, signature.seed)
+    }
+    None => fail(format!(
+      "no config with digest {} found",
+      signature.config_digest
+    )),
+  }
+}
+
+// Write the signature of the run beside its outputs.
+// Create the directory of the render and write its manifest there.
+fn prepare_output(model: &Model) -> Result<(), Box<dyn std::error::Error>> {
+  // Make sure the directory where we will save images to exists.
+  std::fs::create_dir_all(capture_directory(model, 0))?;
+  model
+    .signature()
+    .save_manifest(&output_directory(model), *MANIFEST)?;
+  Ok(())
+}
+
+// The directory where we'll save the outputs of the run.
+fn output_directory(model: &Model) -> std::path::PathBuf {
+  display::project_path()
     .expect("Could not locate project_path")
     .join("frames")
     .join("spinners")
     .join(NAME.as_str())
     .join(model.signature().generate_filename())
-    .join(elapsed_frames.to_string())
+}
+
+// The directory where we'll save the frames.
+fn capture_directory(model: &Model, elapsed_frames: u64) -> std::path::PathBuf {
+  output_directory(model).join(elapsed_frames.to_string())
 }
@@ -1,22 +1,56 @@
-use crate::config::Config;
+use crate::config::{Config, ConfigWatcher};
 use crate::spinner::{Spinner, SpinnerInput};
-use display::DisplayDriver;
+use display::{Controls, DisplayDriver};
 use rand::prelude::{thread_rng, Rng};
 use rand::prelude::{SeedableRng, SmallRng};
 use signature::Signature;
 
+const SKETCH: &str = "spinners";
+
 pub struct Model {
+  pub config: Config,
+  pub watcher: ConfigWatcher,
+  // Why the config file could not be reloaded after its last change, if it could not.
+  pub reload_error: Option<String>,
   pub display_driver: DisplayDriver,
   pub spinners: Vec<Spinner>,
   pub iteration: usize,
+  pub controls: Controls,
+  // Whether the signature is shown over the preview and drawn on the final frame.
+  pub signed: bool,
+  seed: u64,
   signature: Signature,
 }
 
 impl Model {
-  pub fn new(config: &Config, config_path: &str, display_driver: DisplayDriver) -> Self {
-    let seed: u64 = thread_rng().gen();
+  pub fn new(
+    config: Config,
+    config_path: &str,
+    seed: Option<u64>,
+    display_driver: DisplayDriver,
+  ) -> Self {
+    let mut model = Self {
+      config,
+      watcher: ConfigWatcher::new(config_path),
+      reload_error: None,
+      display_driver,
+      spinners: Vec::new(),
+      iteration: 0,
+      controls: Controls::default(),
+      signed: true,
+      seed: 0,
+      signature: Signature::default(),
+    };
+    model.reseed(seed);
+    model
+  }
+
+  // Set the spinners up again from the first iteration, with a random seed if none is given.
+  pub fn reseed(&mut self, seed: Option<u64>) {
+    let seed: u64 = seed.unwrap_or_else(|| thread_rng().gen());
     let mut rng = <SmallRng as SeedableRng>::seed_from_u64(seed);
-    let spinners = config
+    let config = &self.config;
+    self.spinners = config
       .spinners
       .iter()
       .map(|spinner_config| {
@@ -27,17 +61,16 @@ impl Model {
         ))
       })
       .collect();
-    let signature = Signature::new(vec![
-      signature::generate_git_hash(),
-      signature::generate_file_hash(config_path),
-      signature::generate_seed_hash(seed),
-    ]);
-    Self {
-      display_driver,
-      spinners,
-      iteration: 0,
-      signature,
-    }
+    self.signature = Signature::new(SKETCH, self.config.digest(), seed)
+      .with_crate_version(SKETCH, env!("CARGO_PKG_VERSION"))
+      .with_crate_version("display", display::VERSION)
+      .with_crate_version("signature", signature::VERSION);
+    self.iteration = 0;
+    self.seed = seed;
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
   }
 
   pub fn signature(&self) -> &Signature {
@@ -119,7 +119,7 @@ impl Spinner {
         .collect();
       return points;
     }
-    return Vec::new();
+    Vec::new()
   }
 
   fn get_density(&self, offset: f32) -> f32 {
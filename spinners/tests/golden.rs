@@ -0,0 +1,31 @@
+use std::path::Path;
+use std::process::Command;
+
+// Render the sketch on the CPU from a fixed config and seed, and compare its last frame to the
+// golden image checked in next to this file. Set UPDATE_GOLDEN to replace the image instead.
+#[test]
+fn matches_golden_image() {
+  let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+  let archive = std::env::temp_dir().join("spinners-golden-archive");
+  let mut command = Command::new(env!("CARGO_BIN_EXE_spinners"));
+  command
+    .arg("--headless")
+    .arg("--unsigned")
+    .args(["--name", "golden"])
+    .args(["--seed", "7"])
+    .arg("--config-file")
+    .arg(directory.join("spinners.toml"))
+    .arg("--config-archive")
+    .arg(&archive)
+    .arg("--golden")
+    .arg(directory.join("spinners.png"));
+  if std::env::var_os("UPDATE_GOLDEN").is_some() {
+    command.arg("--update-golden");
+  }
+  let output = command.output().expect("the sketch could not be run");
+  assert!(
+    output.status.success(),
+    "{}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+}
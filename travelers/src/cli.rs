@@ -0,0 +1,60 @@
+use clap::{CommandFactory, FromArgMatches, Parser};
+use display::{Canvas, OutputArgs, PaperSize, Preview, ToneMapping};
+
+pub fn parse_cli_args() -> CliArgs {
+  // Golden runs stop on their own, after the given number of iterations.
+  let command = CliArgs::command().mut_arg("golden", |arg| arg.requires("iterations"));
+  CliArgs::from_arg_matches(&command.get_matches()).unwrap_or_else(|err| err.exit())
+}
+
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+pub struct CliArgs {
+  /// Paper size of the print: a0 to a6, letter, legal, tabloid or <width>x<height> in mm, cm or in
+  #[clap(long)]
+  pub paper: Option<PaperSize>,
+  /// Printing resolution, in dots per inch
+  #[clap(long, default_value_t = 300.)]
+  pub dpi: f32,
+  /// Bleed around the paper, in millimeters
+  #[clap(long, default_value_t = 0.)]
+  pub bleed: f32,
+  /// Margins keeping the content away from the paper edges, in millimeters
+  #[clap(long, default_value_t = 0.)]
+  pub margin: f32,
+  /// Print the paper in landscape orientation
+  #[clap(long)]
+  pub landscape: bool,
+  /// Render on the CPU without opening a preview window, for the given number of iterations
+  #[clap(long, requires = "iterations")]
+  pub headless: bool,
+  /// Preview window size: <max side> in pixels, scale:<factor> of the texture, or none to render
+  /// on the GPU without a window for the given number of iterations
+  #[clap(long, requires_if("none", "iterations"))]
+  pub preview: Option<Preview>,
+  /// Number of frames to render, unbounded in the preview window if not set
+  #[clap(short, long)]
+  pub iterations: Option<u64>,
+  /// Accumulate the travelers into a density buffer tone mapped with log, gamma:<gamma> or
+  /// reinhard:<exposure>, instead of drawing them opaque
+  #[clap(long)]
+  pub density: Option<ToneMapping>,
+  #[clap(flatten)]
+  pub output: OutputArgs,
+}
+
+impl CliArgs {
+  // The print described by the arguments, a texture of `default_size` pixels if no paper is given.
+  pub fn canvas(&self, default_size: [u32; 2]) -> Canvas {
+    let canvas = match self.paper {
+      Some(paper) => Canvas::new(paper, self.dpi),
+      None => Canvas::from_pixels(default_size, self.dpi),
+    };
+    Canvas {
+      bleed: self.bleed,
+      margin: self.margin,
+      landscape: self.landscape,
+      ..canvas
+    }
+  }
+}
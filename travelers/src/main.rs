@@ -1,24 +1,44 @@
+mod cli;
 mod model;
 mod strategy;
 mod traveler;
 
+use crate::cli::parse_cli_args;
 use crate::model::Model;
 use crate::strategy::{apply_strategy, Strategy};
 use crate::traveler::Traveler;
-use display::DisplayDriver;
+use display::run::{check_golden, fail, finish};
+use display::{
+    Canvas, Command, Controls, DisplayDriver, EncoderOptions, FailurePolicy, GoldenOptions,
+    LayerOptions, OutputFormat, Preview, SavePolicy, TilingOptions, WriterOptions,
+};
+use lazy_static::lazy_static;
 use nannou::prelude::*;
-use rand::Rng;
+use rand::rngs::{SmallRng, StdRng};
+use rand::{Rng, SeedableRng};
 use std::f32::consts::PI;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-const WIDTH: u32 = 1470;
-const HEIGHT: u32 = 1470;
+// Size of the texture when no paper is given.
+const DEFAULT_SIZE: [u32; 2] = [1470, 1470];
 const N_TRAVELERS: usize = 200;
 const MAX_VELOCITY: f32 = 3.;
 const MAX_FORCE: f32 = 0.1;
 const MAX_POINTS: u32 = 100;
 const SEEK_RANGE: usize = 7;
-const DISPLAY_FACTOR: u32 = 2;
+// Largest side of the preview window by default, in pixels.
+const PREVIEW_SIZE: u32 = 735;
+// The travelers are not signed.
+const COMMANDS: [Command; 5] = [
+    Command::TogglePause,
+    Command::Step,
+    Command::Snapshot,
+    Command::Restart,
+    Command::ToggleHelp,
+];
+// The travelers are drawn on their own layer, above the background.
+const TRAVELERS_LAYER: &str = "travelers";
 const COLORS: [(u8, u8, u8); 15] = [
     (17, 21, 46),
     (19, 29, 120),
@@ -37,27 +57,118 @@ const COLORS: [(u8, u8, u8); 15] = [
     (255, 222, 179),
 ];
 
+lazy_static! {
+    static ref CANVAS: Canvas = parse_cli_args().canvas(DEFAULT_SIZE);
+    static ref HEADLESS: bool = parse_cli_args().headless;
+    static ref PREVIEW: Preview = parse_cli_args()
+        .preview
+        .unwrap_or(Preview::Fit(PREVIEW_SIZE));
+    // Whether the frames are rendered in a loop of our own rather than by a window.
+    static ref WINDOWLESS: bool = *HEADLESS || *PREVIEW == Preview::Hidden;
+    static ref ITERATIONS: Option<u64> = parse_cli_args().iterations;
+    static ref FORMATS: Vec<OutputFormat> = parse_cli_args().output.formats;
+    // Whether the segments between the travelers and their targets are recorded for pen plotters.
+    static ref PLOTTING: bool = FORMATS.iter().any(OutputFormat::is_plot);
+    static ref SAVE_POLICY: SavePolicy = parse_cli_args().output.save_policy.unwrap_or_default();
+    static ref FAILURE_POLICY: FailurePolicy = parse_cli_args().output.on_failure;
+    static ref WRITERS: WriterOptions = parse_cli_args().output.writer_options();
+    static ref SEED: u64 = parse_cli_args()
+        .output.seed
+        .unwrap_or_else(|| rand::thread_rng().gen());
+    static ref GOLDEN: Option<PathBuf> = parse_cli_args().output.golden;
+    static ref GOLDEN_OPTIONS: GoldenOptions = parse_cli_args().output.golden_options();
+    static ref TILING: Option<TilingOptions> = parse_cli_args().output.tiling_options();
+    static ref ENCODER: Option<EncoderOptions> =
+        parse_cli_args().output.encoder_options();
+}
+
 fn main() {
+    if *WINDOWLESS {
+        return headless();
+    }
     nannou::app(model).update(update).exit(exit).run();
 }
 
 fn model(app: &App) -> Model {
-    let texture_size = [WIDTH, HEIGHT];
+    let texture_size = CANVAS.texture_size();
     // Create the window.
-    let [win_w, win_h] = [
-        texture_size[0] / DISPLAY_FACTOR,
-        texture_size[1] / DISPLAY_FACTOR,
-    ];
+    let [win_w, win_h] = PREVIEW
+        .window_size(texture_size)
+        .expect("the preview window is hidden");
     let w_id = app
         .new_window()
         .size(win_w, win_h)
         .view(view)
+        .key_pressed(key_pressed)
         .build()
         .unwrap();
     let window = app.window(w_id).unwrap();
 
+    eprintln!("Press H in the preview window for the keyboard controls");
+    init_model(DisplayDriver::new(&window, texture_size))
+}
+
+fn headless() {
+    let display_driver = match *HEADLESS {
+        true => DisplayDriver::headless(CANVAS.texture_size()),
+        false => DisplayDriver::offscreen(CANVAS.texture_size()),
+    };
+    let mut model = init_model(display_driver);
+    while ITERATIONS.is_none_or(|iterations| model.frame < iterations) {
+        step(&mut model);
+    }
+    if let Some(golden) = &*GOLDEN {
+        check_golden(&mut model.display_driver, golden, &GOLDEN_OPTIONS);
+    }
+    finish(&mut model.display_driver);
+}
+
+fn init_model(display_driver: DisplayDriver) -> Model {
+    let mut display_driver = display_driver
+        .with_formats(&FORMATS)
+        .with_save_policy(SAVE_POLICY.clone())
+        .with_failure_policy(*FAILURE_POLICY)
+        .with_writers(*WRITERS)
+        .with_plotter(parse_cli_args().output.plotter_options(*CANVAS))
+        .with_layer(
+            TRAVELERS_LAYER,
+            LayerOptions {
+                density: parse_cli_args().density,
+                ..Default::default()
+            },
+        );
+    if let Some(tiling) = *TILING {
+        display_driver = display_driver.with_tiling(tiling);
+    }
+    if let Some(progress) = parse_cli_args().output.progress(*ITERATIONS) {
+        display_driver = display_driver.with_progress(progress);
+    }
+    if FORMATS.contains(&OutputFormat::Pdf) {
+        display_driver = display_driver.with_pdf(parse_cli_args().output.pdf_options(*CANVAS));
+    }
+    // Make sure the directory where we will save images to exists.
+    std::fs::create_dir_all(capture_directory(0)).unwrap();
+    if let Some(options) = ENCODER.clone() {
+        display_driver.set_encoder(output_directory().join("animation"), options);
+    }
+
+    let mut model = Model {
+        display_driver,
+        travelers: Vec::new(),
+        targets: Vec::new(),
+        colors: Vec::new(),
+        controls: Controls::new(&COMMANDS),
+        frame: 0,
+        rng: StdRng::seed_from_u64(*SEED),
+    };
+    spawn(&mut model, *SEED);
+    model
+}
+
+// Scatter the travelers again from the first frame, drawn from `seed`.
+fn spawn(model: &mut Model, seed: u64) {
     let mut travelers = Vec::new();
-    let mut rng = rand::thread_rng();
+    let mut rng = SmallRng::seed_from_u64(seed);
     for _ in 0..N_TRAVELERS {
         let theta = 2. * PI * rng.gen::<f32>();
         travelers.push(Arc::new(Mutex::new(Traveler::new(
@@ -72,7 +183,6 @@ fn model(app: &App) -> Model {
 
     let mut targets = Vec::new();
     let mut colors = Vec::new();
-    let mut rng = rand::thread_rng();
 
     for i in 0..N_TRAVELERS {
         let target = (i + rng.gen_range(1..SEEK_RANGE)) % N_TRAVELERS;
@@ -89,23 +199,29 @@ fn model(app: &App) -> Model {
         colors.push(color);
     }
 
-    // Make sure the directory where we will save images to exists.
-    std::fs::create_dir_all(&capture_directory(app)).unwrap();
+    model.travelers = travelers;
+    model.targets = targets;
+    model.colors = colors;
+    model.frame = 0;
+    model.rng = StdRng::seed_from_u64(seed);
+}
 
-    Model {
-        display_driver: DisplayDriver::new(&window, texture_size),
-        travelers,
-        targets,
-        colors,
+fn update(_app: &App, model: &mut Model, _update: Update) {
+    let running = ITERATIONS.is_none_or(|iterations| model.frame < iterations);
+    if running && model.controls.should_step() {
+        step(model);
     }
+    let overlay = model.controls.overlay();
+    model.display_driver.set_overlay(overlay);
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
-    // Reset the `draw` state.
-    let draw = model.display_driver.draw();
-    draw.reset();
-    if app.elapsed_frames() == 0 {
-        draw.background().color(WHEAT);
+fn step(model: &mut Model) {
+    let elapsed_frames = model.frame;
+    model.frame += 1;
+    // Reset the `draw` state of every layer.
+    model.display_driver.reset();
+    if elapsed_frames == 0 {
+        model.display_driver.draw().background(WHEAT);
     }
 
     let center = Vec2::new(0., 0.);
@@ -113,57 +229,111 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         let mut traveler = traveler.lock().unwrap();
         let target = model.targets.get(index).unwrap();
         let target = target.lock().unwrap();
-        apply_strategy(&mut traveler, &target.position, Strategy::SEEK, MAX_FORCE);
+        apply_strategy(&mut traveler, &target.position, Strategy::Seek, MAX_FORCE);
         let gravity = get_inverse_gravity(&traveler.position, &center, MAX_FORCE);
-        apply_strategy(&mut traveler, &center, Strategy::SEEK, gravity);
+        apply_strategy(&mut traveler, &center, Strategy::Seek, gravity);
         traveler.update();
         let color = model.colors.get(index).unwrap();
-        draw_traveler(&traveler, &target.position, &draw, color);
+        let points = traveler_points(&traveler, &target.position, &mut model.rng);
+        // Splat the points straight into the density buffer when the travelers accumulate.
+        match model.display_driver.density(TRAVELERS_LAYER) {
+            Some(density) => density.splat_points(&points, *color),
+            None => {
+                let draw = model
+                    .display_driver
+                    .layer(TRAVELERS_LAYER)
+                    .expect("the layer was added in init_model");
+                for point in points {
+                    draw.ellipse(point, Vec2::ONE, *color);
+                }
+            }
+        }
+        // Plotters draw the segment the points are scattered along rather than the points.
+        if *PLOTTING {
+            let draw = model
+                .display_driver
+                .layer(TRAVELERS_LAYER)
+                .expect("the layer was added in init_model");
+            draw.plot_path(&[traveler.position, target.position]);
+        }
     }
 
     // Render our drawing to the texture.
-    let window = app.main_window();
-    model.display_driver.save(&window, capture_directory(app));
+    let path = capture_directory(elapsed_frames);
+    if let Err(err) = model.display_driver.update(elapsed_frames, path) {
+        fail(err);
+    }
+}
+
+fn key_pressed(_app: &App, model: &mut Model, key: Key) {
+    match model.controls.key_pressed(key) {
+        Some(Command::Snapshot) => snapshot(model),
+        Some(Command::Restart) => restart(model),
+        _ => {}
+    }
+}
+
+// Save the frame currently shown, whether the sketch is running or not.
+fn snapshot(model: &mut Model) {
+    if model.frame == 0 {
+        return;
+    }
+    let path = capture_directory(model.frame - 1);
+    if let Err(err) = model.display_driver.save(path) {
+        fail(err);
+    }
+}
+
+// Start the render over with a new seed.
+fn restart(model: &mut Model) {
+    if let Err(err) = model.display_driver.restart() {
+        fail(err);
+    }
+    let seed = rand::thread_rng().gen();
+    spawn(model, seed);
+    eprintln!("Restarted with seed {}", seed);
 }
 
 fn view(_app: &App, model: &Model, frame: Frame) {
     model.display_driver.render(frame);
 }
 
-fn exit(app: &App, model: Model) {
-    let window = app.main_window();
-    model.display_driver.wait(&window);
+fn exit(_app: &App, mut model: Model) {
+    finish(&mut model.display_driver);
 }
 
-// The directory where we'll save the frames.
-fn capture_directory(app: &nannou::app::App) -> std::path::PathBuf {
-    let elapsed_frames = app.main_window().elapsed_frames();
-    app.project_path()
+// The directory where we'll save the outputs of the run.
+fn output_directory() -> std::path::PathBuf {
+    display::project_path()
         .expect("Could not locate project_path")
         .join("frames")
         .join("travelers")
-        .join(elapsed_frames.to_string())
 }
 
-pub fn draw_traveler(traveler: &Traveler, target: &Vec2, draw: &Draw, color: &Srgba) {
-    let mut rng = rand::thread_rng();
+// The directory where we'll save the frames.
+fn capture_directory(elapsed_frames: u64) -> std::path::PathBuf {
+    output_directory().join(elapsed_frames.to_string())
+}
+
+// Points scattered along the segment between the traveler and its target.
+pub fn traveler_points<R: Rng>(traveler: &Traveler, target: &Vec2, rng: &mut R) -> Vec<Point2> {
     let middle = (traveler.position + *target) / 2.;
     let direction = *target - traveler.position;
+    let mut points = Vec::with_capacity(2 * traveler.n_points);
     for _ in 0..traveler.n_points {
         let theta = 2. * PI * rng.gen::<f32>();
-        let point = middle + theta.sin() / 2. * direction;
-        draw.ellipse().xy(point).w_h(1., 1.).color(*color);
-        let point = middle - theta.sin() / 2. * direction;
-        draw.ellipse().xy(point).w_h(1., 1.).color(*color);
+        points.push(middle + theta.sin() / 2. * direction);
+        points.push(middle - theta.sin() / 2. * direction);
     }
+    points
 }
 
 fn get_inverse_gravity(a: &Vec2, b: &Vec2, max_force: f32) -> f32 {
     let distance = (*b - *a).length();
-    let limit = WIDTH.min(HEIGHT) as f32;
+    let limit = CANVAS.trim().w().min(CANVAS.trim().h());
     if distance < limit / 2. {
-        return 0.;
+        0.
     } else {
-        return (distance / limit).min(max_force);
+        (distance / limit).min(max_force)
     }
 }
@@ -1,6 +1,7 @@
 use crate::traveler::Traveler;
-use display::DisplayDriver;
+use display::{Controls, DisplayDriver};
 use nannou::prelude::Srgba;
+use rand::rngs::StdRng;
 use std::sync::{Arc, Mutex};
 
 pub struct Model {
@@ -8,4 +9,9 @@ pub struct Model {
   pub travelers: Vec<Arc<Mutex<Traveler>>>,
   pub targets: Vec<Arc<Mutex<Traveler>>>,
   pub colors: Vec<Srgba>,
+  pub controls: Controls,
+  // Index of the next frame.
+  pub frame: u64,
+  // Scatters the points of the travelers, seeded with the seed of the run.
+  pub rng: StdRng,
 }
@@ -2,7 +2,7 @@ use crate::traveler::Traveler;
 use nannou::prelude::Vec2;
 
 pub enum Strategy {
-  SEEK,
+  Seek,
 }
 
 pub fn apply_strategy(
@@ -12,7 +12,7 @@ pub fn apply_strategy(
   max_force: f32,
 ) {
   match strategy {
-    Strategy::SEEK => seek(traveler, position, max_force),
+    Strategy::Seek => seek(traveler, position, max_force),
   }
 }
 
@@ -0,0 +1,28 @@
+use std::path::Path;
+use std::process::Command;
+
+// Render the sketch on the CPU for a few frames from a fixed seed, and compare its last frame to the
+// golden image checked in next to this file. Set UPDATE_GOLDEN to replace the image instead.
+#[test]
+fn matches_golden_image() {
+  let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+  let mut command = Command::new(env!("CARGO_BIN_EXE_travelers"));
+  command
+    .arg("--headless")
+    .args(["--save", "key"])
+    .args(["--seed", "7"])
+    .args(["--iterations", "40"])
+    .args(["--paper", "60x60mm"])
+    .args(["--dpi", "100"])
+    .arg("--golden")
+    .arg(directory.join("travelers.png"));
+  if std::env::var_os("UPDATE_GOLDEN").is_some() {
+    command.arg("--update-golden");
+  }
+  let output = command.output().expect("the sketch could not be run");
+  assert!(
+    output.status.success(),
+    "{}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+}